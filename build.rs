@@ -0,0 +1,45 @@
+use std::path::PathBuf;
+use std::process::Command;
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+
+    #[cfg(feature = "grpc")]
+    grpc();
+
+    // the eBPF program only gets built for the `ebpf` feature, which needs clang with the bpf
+    // target and the libbpf headers
+    if std::env::var_os("CARGO_FEATURE_EBPF").is_none() {
+        return;
+    }
+
+    let source = "ebpf/camera.bpf.c";
+    println!("cargo:rerun-if-changed={}", source);
+    println!("cargo:rerun-if-env-changed=CLANG");
+
+    let out = PathBuf::from(std::env::var_os("OUT_DIR").unwrap()).join("camera.bpf.o");
+    let clang = std::env::var("CLANG").unwrap_or_else(|_| "clang".to_string());
+    let status = Command::new(&clang)
+        .args(["-O2", "-g", "-target", "bpf", "-c", source, "-o"])
+        .arg(&out)
+        .status()
+        .unwrap_or_else(|e| panic!("failed to run {} for the ebpf feature: {}", clang, e));
+
+    if !status.success() {
+        panic!("compiling {} failed with {}", source, status);
+    }
+}
+
+/// generates the gRPC service, the proto gets parsed with protox so there's no protoc to install
+#[cfg(feature = "grpc")]
+fn grpc() {
+    let proto = "proto/camera_snitch.proto";
+    println!("cargo:rerun-if-changed={}", proto);
+
+    let descriptors = protox::compile([proto], ["proto"])
+        .unwrap_or_else(|e| panic!("parsing {} failed: {}", proto, e));
+    tonic_build::configure()
+        .build_client(false)
+        .compile_fds(descriptors)
+        .unwrap_or_else(|e| panic!("generating the gRPC service failed: {}", e));
+}
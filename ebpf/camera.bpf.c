@@ -0,0 +1,158 @@
+// SPDX-License-Identifier: GPL-2.0
+//
+// tracks opens and closes of /dev/video* per process for the `ebpf` backend, see
+// src/monitor/ebpf.rs for the userspace side
+//
+// build with: clang -O2 -g -target bpf -c ebpf/camera.bpf.c -o camera.bpf.o
+
+#include <linux/bpf.h>
+#include <linux/types.h>
+#include <bpf/bpf_helpers.h>
+
+#define EVENT_OPEN 0
+#define EVENT_CLOSE 1
+#define EVENT_EXIT 2
+
+#define PREFIX "/dev/video"
+#define PREFIX_LEN 10
+
+// layout has to match `RawEvent` in src/monitor/ebpf.rs
+struct event {
+	__u32 tgid;
+	__u32 minor;
+	__u32 kind;
+	char comm[16];
+};
+
+struct fd_key {
+	__u32 tgid;
+	__u32 fd;
+};
+
+// device number of an openat in flight, keyed by pid_tgid, until we see its return value
+struct {
+	__uint(type, BPF_MAP_TYPE_HASH);
+	__uint(max_entries, 1024);
+	__type(key, __u64);
+	__type(value, __u32);
+} PENDING_OPENS SEC(".maps");
+
+// device number behind each open video fd
+struct {
+	__uint(type, BPF_MAP_TYPE_HASH);
+	__uint(max_entries, 4096);
+	__type(key, struct fd_key);
+	__type(value, __u32);
+} OPEN_FDS SEC(".maps");
+
+struct {
+	__uint(type, BPF_MAP_TYPE_RINGBUF);
+	__uint(max_entries, 64 * 1024);
+} EVENTS SEC(".maps");
+
+// see /sys/kernel/tracing/events/syscalls/sys_enter_openat/format
+struct sys_enter_ctx {
+	__u64 common;
+	long id;
+	unsigned long args[6];
+};
+
+struct sys_exit_ctx {
+	__u64 common;
+	long id;
+	long ret;
+};
+
+static __always_inline void emit(__u32 tgid, __u32 minor, __u32 kind)
+{
+	struct event *event = bpf_ringbuf_reserve(&EVENTS, sizeof(*event), 0);
+	if (!event)
+		return;
+
+	event->tgid = tgid;
+	event->minor = minor;
+	event->kind = kind;
+	bpf_get_current_comm(&event->comm, sizeof(event->comm));
+	bpf_ringbuf_submit(event, 0);
+}
+
+SEC("tracepoint/syscalls/sys_enter_openat")
+int enter_openat(struct sys_enter_ctx *ctx)
+{
+	char path[PREFIX_LEN + 4] = {};
+	const char prefix[] = PREFIX;
+
+	if (bpf_probe_read_user_str(path, sizeof(path), (const char *)ctx->args[1]) <= PREFIX_LEN)
+		return 0;
+
+#pragma unroll
+	for (int i = 0; i < PREFIX_LEN; i++) {
+		if (path[i] != prefix[i])
+			return 0;
+	}
+
+	__u32 minor = 0;
+#pragma unroll
+	for (int i = PREFIX_LEN; i < PREFIX_LEN + 3; i++) {
+		if (path[i] == '\0')
+			break;
+		if (path[i] < '0' || path[i] > '9')
+			return 0;
+		minor = minor * 10 + (path[i] - '0');
+	}
+
+	__u64 pid_tgid = bpf_get_current_pid_tgid();
+	bpf_map_update_elem(&PENDING_OPENS, &pid_tgid, &minor, BPF_ANY);
+	return 0;
+}
+
+SEC("tracepoint/syscalls/sys_exit_openat")
+int exit_openat(struct sys_exit_ctx *ctx)
+{
+	__u64 pid_tgid = bpf_get_current_pid_tgid();
+	__u32 *minor = bpf_map_lookup_elem(&PENDING_OPENS, &pid_tgid);
+	if (!minor)
+		return 0;
+
+	__u32 device = *minor;
+	bpf_map_delete_elem(&PENDING_OPENS, &pid_tgid);
+	if (ctx->ret < 0)
+		return 0;
+
+	struct fd_key key = { .tgid = pid_tgid >> 32, .fd = ctx->ret };
+	bpf_map_update_elem(&OPEN_FDS, &key, &device, BPF_ANY);
+	emit(key.tgid, device, EVENT_OPEN);
+	return 0;
+}
+
+SEC("tracepoint/syscalls/sys_enter_close")
+int enter_close(struct sys_enter_ctx *ctx)
+{
+	struct fd_key key = { .tgid = bpf_get_current_pid_tgid() >> 32, .fd = ctx->args[0] };
+	__u32 *minor = bpf_map_lookup_elem(&OPEN_FDS, &key);
+	if (!minor)
+		return 0;
+
+	__u32 device = *minor;
+	bpf_map_delete_elem(&OPEN_FDS, &key);
+	emit(key.tgid, device, EVENT_CLOSE);
+	return 0;
+}
+
+// fds still open when a process exits are closed by the kernel without a close() call, userspace
+// closes whatever it still has recorded for the process. stale OPEN_FDS entries get overwritten
+// when the fd number is reused
+SEC("tracepoint/sched/sched_process_exit")
+int process_exit(void *ctx)
+{
+	__u64 pid_tgid = bpf_get_current_pid_tgid();
+
+	// only the thread group leader exiting means the process is gone
+	if ((__u32)pid_tgid != (__u32)(pid_tgid >> 32))
+		return 0;
+
+	emit(pid_tgid >> 32, 0, EVENT_EXIT);
+	return 0;
+}
+
+char LICENSE[] SEC("license") = "GPL";
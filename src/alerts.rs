@@ -0,0 +1,169 @@
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use chrono::Local;
+
+use crate::config::{AlertConfig, AlertService, Config};
+use crate::process::ProcessInfo;
+use crate::schedule::Window;
+
+/// where an alert gets pushed to
+#[derive(Debug)]
+enum Service {
+    Ntfy { url: String, token: Option<String> },
+    Pushover { token: String, user: String },
+    Telegram { token: String, chat_id: String },
+}
+
+#[derive(Debug)]
+struct Channel {
+    service: Service,
+    unexpected_only: bool,
+    outside_hours: Option<Window>,
+    min_interval: Duration,
+    last_sent: Option<Instant>,
+}
+
+impl Channel {
+    fn from_config(alert: &AlertConfig) -> anyhow::Result<Self> {
+        let required = |field: &Option<String>, name: &str| {
+            field
+                .clone()
+                .ok_or_else(|| anyhow::anyhow!("{:?} alerts need `{}`", alert.service, name))
+        };
+
+        let service = match alert.service {
+            AlertService::Ntfy => Service::Ntfy {
+                url: required(&alert.url, "url")?,
+                token: alert.token.clone(),
+            },
+            AlertService::Pushover => Service::Pushover {
+                token: required(&alert.token, "token")?,
+                user: required(&alert.user, "user")?,
+            },
+            AlertService::Telegram => Service::Telegram {
+                token: required(&alert.token, "token")?,
+                chat_id: required(&alert.chat_id, "chat_id")?,
+            },
+        };
+
+        Ok(Self {
+            service,
+            unexpected_only: alert.unexpected_only,
+            outside_hours: alert.outside_hours,
+            min_interval: Duration::from_secs(alert.min_interval),
+            last_sent: None,
+        })
+    }
+}
+
+/// push notifications straight to a phone when the camera turns on, independent of HA
+pub struct Alerts {
+    http: reqwest::Client,
+    channels: Vec<Channel>,
+    expected: Vec<String>,
+}
+
+impl Alerts {
+    /// the alert channels from the config, `None` if there aren't any
+    pub fn from_config(config: &Config) -> anyhow::Result<Option<Self>> {
+        if config.alerts.is_empty() {
+            return Ok(None);
+        }
+
+        let channels = config
+            .alerts
+            .iter()
+            .map(Channel::from_config)
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(Some(Self {
+            http: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()?,
+            channels,
+            expected: config.expected_processes.clone(),
+        }))
+    }
+
+    /// true if something not on the expected list is using the camera, not knowing who it is
+    /// counts as unexpected
+    fn unexpected(&self, openers: &[ProcessInfo]) -> bool {
+        openers.is_empty() || openers.iter().any(|p| !p.is_expected(&self.expected))
+    }
+
+    /// sends the alert to every channel whose filters and rate limit let it through, in the
+    /// background so a slow service can't hold anything up
+    #[tracing::instrument(skip(self, openers))]
+    pub fn camera_on(&mut self, device: &Path, openers: &[ProcessInfo]) {
+        let unexpected = self.unexpected(openers);
+        let now = Local::now().time();
+
+        let message = match crate::process::describe(openers) {
+            Some(used_by) => format!("camera {} turned on by {}", device.display(), used_by),
+            None => format!("camera {} turned on", device.display()),
+        };
+
+        for channel in &mut self.channels {
+            if channel.unexpected_only && !unexpected {
+                continue;
+            }
+            if channel
+                .outside_hours
+                .is_some_and(|hours| hours.contains(now))
+            {
+                continue;
+            }
+            if channel
+                .last_sent
+                .is_some_and(|at| at.elapsed() < channel.min_interval)
+            {
+                tracing::debug!("rate limited alert to {:?}", channel.service);
+                continue;
+            }
+            channel.last_sent = Some(Instant::now());
+
+            let request = match &channel.service {
+                Service::Ntfy { url, token } => {
+                    let request = self
+                        .http
+                        .post(url)
+                        .header("Title", "Camera on")
+                        .header("Tags", "camera")
+                        .body(message.clone());
+                    match token {
+                        Some(token) => request.bearer_auth(token),
+                        None => request,
+                    }
+                }
+                Service::Pushover { token, user } => self
+                    .http
+                    .post("https://api.pushover.net/1/messages.json")
+                    .json(&serde_json::json!({
+                        "token": token,
+                        "user": user,
+                        "title": "Camera on",
+                        "message": message,
+                    })),
+                Service::Telegram { token, chat_id } => self
+                    .http
+                    .post(format!("https://api.telegram.org/bot{}/sendMessage", token))
+                    .json(&serde_json::json!({
+                        "chat_id": chat_id,
+                        "text": message,
+                    })),
+            };
+
+            tokio::spawn(async move {
+                let res = request
+                    .send()
+                    .await
+                    .and_then(reqwest::Response::error_for_status);
+                if let Err(e) = res {
+                    // the telegram token is part of the url, keep it out of the logs
+                    tracing::error!("error sending alert: {}", e.without_url());
+                }
+            });
+        }
+    }
+}
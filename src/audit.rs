@@ -0,0 +1,142 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+
+use crate::process::ProcessInfo;
+
+/// one line of the audit log
+#[derive(serde::Serialize, Debug)]
+pub struct AuditEntry<'a> {
+    pub timestamp: String,
+    pub device: &'a Path,
+    /// `opened`, `closed`, `added` or `removed`
+    pub transition: &'static str,
+    /// processes holding the device open, only known for opens
+    pub processes: &'a [ProcessInfo],
+    /// the event would have changed the state but landed inside the debounce window, so it only
+    /// gets published once the window closes
+    pub debounce_suppressed: bool,
+}
+
+/// append-only JSONL log of every device event, rotated once it grows past `max_size` bytes
+pub struct AuditLog {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_size: u64,
+    /// how many rotated files (`<path>.1` being the newest) to keep around
+    keep: u32,
+    /// the last rotation failed, it gets retried on every write but only reported once
+    rotate_failed: bool,
+}
+
+fn open_append(path: &Path) -> anyhow::Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("opening audit log {}", path.display()))
+}
+
+impl AuditLog {
+    pub fn open(path: &Path, max_size: u64, keep: u32) -> anyhow::Result<Self> {
+        let file = open_append(path)?;
+        let size = file.metadata()?.len();
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            size,
+            max_size,
+            keep,
+            rotate_failed: false,
+        })
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub fn write(&mut self, entry: &AuditEntry<'_>) {
+        if let Err(e) = self.try_write(entry) {
+            tracing::error!("error writing audit log: {}", e);
+        }
+    }
+
+    fn try_write(&mut self, entry: &AuditEntry<'_>) -> anyhow::Result<()> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+
+        // a log that can't rotate grows past the limit rather than losing entries
+        if self.size > 0 && self.size + line.len() as u64 > self.max_size {
+            match self.rotate() {
+                Ok(()) => self.rotate_failed = false,
+                Err(e) if !self.rotate_failed => {
+                    tracing::error!(
+                        "error rotating audit log, appending to {} until it works: {}",
+                        self.path.display(),
+                        e
+                    );
+                    self.rotate_failed = true;
+                }
+                Err(_) => {}
+            }
+        }
+
+        self.file.write_all(&line)?;
+        self.size += line.len() as u64;
+
+        Ok(())
+    }
+
+    /// shifts `<path>.N` to `<path>.N+1`, dropping the oldest, and starts a fresh file
+    fn rotate(&mut self) -> anyhow::Result<()> {
+        let rotated = |n: u32| PathBuf::from(format!("{}.{}", self.path.display(), n));
+
+        if self.keep == 0 {
+            std::fs::remove_file(&self.path)?;
+        } else {
+            for n in (1..self.keep).rev() {
+                let from = rotated(n);
+                if from.exists() {
+                    std::fs::rename(&from, rotated(n + 1))?;
+                }
+            }
+            std::fs::rename(&self.path, rotated(1))?;
+        }
+
+        self.file = open_append(&self.path)?;
+        self.size = 0;
+        tracing::info!("rotated audit log {}", self.path.display());
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_writing_when_rotation_fails() {
+        let dir = std::env::temp_dir().join(format!("camera-snitch-{}", crate::host::random_id(8)));
+        std::fs::create_dir(&dir).unwrap();
+        let path = dir.join("audit.jsonl");
+        // a non-empty directory where the rotated file should go, so the rename fails
+        std::fs::create_dir_all(dir.join("audit.jsonl.1").join("in-the-way")).unwrap();
+
+        let mut log = AuditLog::open(&path, 1, 1).unwrap();
+        for transition in ["opened", "closed", "opened"] {
+            log.write(&AuditEntry {
+                timestamp: String::new(),
+                device: Path::new("/dev/video0"),
+                transition,
+                processes: &[],
+                debounce_suppressed: false,
+            });
+        }
+
+        let lines = std::fs::read_to_string(&path).unwrap().lines().count();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(lines, 3);
+    }
+}
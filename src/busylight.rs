@@ -0,0 +1,209 @@
+//! drives a USB status light directly over HID, behind the `busylight` feature
+
+use std::str::FromStr;
+use std::sync::mpsc;
+use std::time::Duration;
+
+use anyhow::Context;
+
+use crate::CameraState;
+
+#[derive(clap::Args, Debug)]
+pub struct BusylightArgs {
+    /// drive a USB busylight (Luxafor Flag, Embrava Blynclight or Kuando BusyLight) on state changes
+    #[clap(long)]
+    busylight: bool,
+    /// color to show while the camera is on, as `rrggbb`
+    #[clap(long, default_value = "ff0000")]
+    busylight_on_color: Color,
+    /// color to show while the camera is off, `000000` turns the light off
+    #[clap(long, default_value = "000000")]
+    busylight_off_color: Color,
+    /// brightness in percent, applied on top of the colors
+    #[clap(long, default_value = "100", value_parser = clap::value_parser!(u8).range(0..=100))]
+    busylight_brightness: u8,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Color {
+    r: u8,
+    g: u8,
+    b: u8,
+}
+
+impl FromStr for Color {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = s.trim_start_matches('#');
+        if hex.len() != 6 {
+            anyhow::bail!("expected a color like ff0000, got {}", s);
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16);
+
+        Ok(Color {
+            r: channel(0)?,
+            g: channel(2)?,
+            b: channel(4)?,
+        })
+    }
+}
+
+impl Color {
+    fn scaled(self, brightness: u8) -> Self {
+        let scale = |c: u8| (c as u16 * brightness as u16 / 100) as u8;
+        Color {
+            r: scale(self.r),
+            g: scale(self.g),
+            b: scale(self.b),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Model {
+    Luxafor,
+    Blynclight,
+    Kuando,
+}
+
+/// (vendor id, product id, model) of the lights we know how to talk to
+const KNOWN_DEVICES: &[(u16, u16, Model)] = &[
+    (0x04d8, 0xf372, Model::Luxafor),
+    (0x2c0d, 0x0001, Model::Blynclight),
+    (0x2c0d, 0x000c, Model::Blynclight),
+    (0x0e53, 0x2516, Model::Blynclight),
+    (0x0e53, 0x2517, Model::Blynclight),
+    (0x27bb, 0x3bca, Model::Kuando),
+    (0x27bb, 0x3bcb, Model::Kuando),
+    (0x27bb, 0x3bcd, Model::Kuando),
+    (0x27bb, 0x3bcf, Model::Kuando),
+];
+
+/// the Kuando lights switch themselves off if they don't hear from the host for ~30 seconds
+const KEEPALIVE: Duration = Duration::from_secs(20);
+
+impl Model {
+    /// output report for a solid color, including the leading report id
+    fn report(self, color: Color) -> Vec<u8> {
+        let Color { r, g, b } = color;
+
+        match self {
+            // report id, "static color" command, all leds
+            Model::Luxafor => vec![0x00, 0x01, 0xff, r, g, b, 0x00, 0x00, 0x00],
+            // note the blue/green order, the control byte's low bit switches the light off
+            Model::Blynclight => {
+                let off = (color == Color { r: 0, g: 0, b: 0 }) as u8;
+                vec![0x00, r, b, g, off, 0x00, 0x00, 0xff, 0x22]
+            }
+            // one "jump to step 0" step with 0-100 channels, then the fixed trailer and a
+            // checksum over everything before it
+            Model::Kuando => {
+                let pct = |c: u8| (c as u16 * 100 / 255) as u8;
+                let mut report = vec![0u8; 65];
+                report[1..9].copy_from_slice(&[
+                    0x10,
+                    0x00,
+                    pct(r),
+                    pct(g),
+                    pct(b),
+                    0x00,
+                    0x00,
+                    0x80,
+                ]);
+                report[60..63].copy_from_slice(&[0xff, 0xff, 0xff]);
+                let checksum: u16 = report[1..63].iter().map(|&b| b as u16).sum();
+                report[63..65].copy_from_slice(&checksum.to_be_bytes());
+                report
+            }
+        }
+    }
+}
+
+/// handle to the thread that owns the HID device, hidapi is blocking so it lives off the runtime
+pub struct Busylight {
+    tx: mpsc::Sender<Color>,
+    worker: Option<std::thread::JoinHandle<()>>,
+    on_color: Color,
+    off_color: Color,
+}
+
+impl Busylight {
+    /// opens the first known light that is plugged in, `None` unless `--busylight` was passed
+    pub fn from_args(args: &BusylightArgs) -> anyhow::Result<Option<Self>> {
+        if !args.busylight {
+            return Ok(None);
+        }
+
+        let api = hidapi::HidApi::new().context("initializing hidapi")?;
+        let (info, model) = api
+            .device_list()
+            .find_map(|info| {
+                KNOWN_DEVICES
+                    .iter()
+                    .find(|(vid, pid, _)| *vid == info.vendor_id() && *pid == info.product_id())
+                    .map(|(_, _, model)| (info, *model))
+            })
+            .context("no supported busylight found")?;
+
+        tracing::info!("using {:?} busylight at {:?}", model, info.path());
+        let device = info.open_device(&api).context("opening busylight")?;
+
+        let (tx, rx) = mpsc::channel::<Color>();
+        let worker = std::thread::spawn(move || {
+            let mut color = Color { r: 0, g: 0, b: 0 };
+            loop {
+                let done = match rx.recv_timeout(KEEPALIVE) {
+                    Ok(next) => {
+                        color = next;
+                        false
+                    }
+                    Err(mpsc::RecvTimeoutError::Timeout) => false,
+                    // the daemon is done, dark rather than whatever it last showed, the idle
+                    // color included
+                    Err(mpsc::RecvTimeoutError::Disconnected) => {
+                        color = Color { r: 0, g: 0, b: 0 };
+                        true
+                    }
+                };
+                if let Err(e) = device.write(&model.report(color)) {
+                    tracing::error!("error writing to busylight: {}", e);
+                }
+                if done {
+                    return;
+                }
+            }
+        });
+
+        let light = Self {
+            tx,
+            worker: Some(worker),
+            on_color: args.busylight_on_color.scaled(args.busylight_brightness),
+            off_color: args.busylight_off_color.scaled(args.busylight_brightness),
+        };
+        light.set_state(&CameraState::Off);
+
+        Ok(Some(light))
+    }
+
+    pub fn set_state(&self, state: &CameraState) {
+        let color = match state {
+            CameraState::On => self.on_color,
+            CameraState::Off => self.off_color,
+        };
+        if self.tx.send(color).is_err() {
+            tracing::error!("busylight thread is gone");
+        }
+    }
+}
+
+impl Drop for Busylight {
+    /// hang up so the thread turns the light off, not to the idle color, and wait for it to have
+    /// written that before the process exits under it
+    fn drop(&mut self) {
+        self.tx = mpsc::channel().0;
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
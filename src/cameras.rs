@@ -0,0 +1,80 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use crate::CameraState;
+
+/// groups the video nodes that belong to one physical camera, like the RGB and IR nodes of a
+/// Windows Hello camera or the capture and metadata nodes of most UVC cameras
+///
+/// each camera goes by its first node, which is what its entities are named after. nodes that are
+/// gone keep their camera, so the events that come in as they go still land on the right one
+#[derive(Debug, Default)]
+pub struct Cameras {
+    /// node to the first node of its camera
+    camera: HashMap<PathBuf, PathBuf>,
+    /// node to the hardware it belongs to, see `v4l::physical_device`
+    physical: HashMap<PathBuf, PathBuf>,
+}
+
+impl Cameras {
+    pub fn new(devices: &[PathBuf]) -> Self {
+        let mut cameras = Self::default();
+        for device in devices {
+            cameras.add(device, devices);
+        }
+
+        cameras
+    }
+
+    /// works out which camera `node` is part of, joining one with another of the `present` nodes
+    /// on the same hardware or else starting its own
+    pub fn add(&mut self, node: &Path, present: &[PathBuf]) {
+        let camera = match crate::v4l::physical_device(node) {
+            Some(physical) => {
+                let camera = present
+                    .iter()
+                    .filter(|other| *other != node)
+                    .find(|other| self.physical.get(*other) == Some(&physical))
+                    .map(|other| self.camera(other).to_path_buf());
+                self.physical.insert(node.to_path_buf(), physical);
+                camera
+            }
+            None => {
+                self.physical.remove(node);
+                None
+            }
+        };
+
+        let camera = camera.unwrap_or_else(|| node.to_path_buf());
+        if camera != node {
+            tracing::info!("{:?} is part of camera {:?}", node, camera);
+        }
+        self.camera.insert(node.to_path_buf(), camera);
+    }
+
+    /// the camera `node` belongs to, by its first node
+    pub fn camera<'a>(&'a self, node: &'a Path) -> &'a Path {
+        self.camera.get(node).map(PathBuf::as_path).unwrap_or(node)
+    }
+
+    /// the cameras with at least one of the `present` nodes
+    pub fn present(&self, present: &[PathBuf]) -> Vec<PathBuf> {
+        let mut cameras: Vec<PathBuf> = Vec::new();
+        for node in present {
+            let camera = self.camera(node);
+            if !cameras.iter().any(|known| known == camera) {
+                cameras.push(camera.to_path_buf());
+            }
+        }
+
+        cameras
+    }
+
+    /// on while any of the camera's nodes are open
+    pub fn state(&self, camera: &Path, open: &HashSet<PathBuf>) -> CameraState {
+        match open.iter().any(|node| self.camera(node) == camera) {
+            true => CameraState::On,
+            false => CameraState::Off,
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::Context;
+use serde::Deserialize;
+
+/// settings that don't fit on the command line, loaded from the `--config` TOML file
+///
+/// ```toml
+/// [devices."/dev/video0"]
+/// on_camera_on = "~/bin/keylight on"
+/// on_camera_off = "~/bin/keylight off"
+/// debounce = 1000
+///
+/// [[mirrors]]
+/// topic = "wled/officelight/api"
+/// payload_on = '{"on": true}'
+/// payload_off = '{"on": false}'
+///
+/// [[alerts]]
+/// service = "ntfy"
+/// url = "https://ntfy.sh/my-camera"
+///
+/// [topics]
+/// state = "home/{hostname}/camera/state"
+/// device_state = "home/{hostname}/camera/{device}/state"
+/// ```
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// per-device overrides, keyed by device path
+    pub devices: HashMap<PathBuf, DeviceConfig>,
+    /// extra raw topics that get a payload on every state change, for consumers that aren't HA
+    pub mirrors: Vec<MirrorConfig>,
+    /// push notifications to send when the camera turns on
+    pub alerts: Vec<AlertConfig>,
+    /// process names or app ids that are expected to use the camera
+    pub expected_processes: Vec<String>,
+    pub quiet_hours: crate::schedule::QuietHours,
+    pub security: SecurityConfig,
+    pub peers: PeersConfig,
+    pub topics: TopicsConfig,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct DeviceConfig {
+    /// shell command to run when this device turns the camera on, replaces `--on-camera-on`
+    pub on_camera_on: Option<String>,
+    /// shell command to run when this device turns the camera off, replaces `--on-camera-off`
+    pub on_camera_off: Option<String>,
+    /// debounce window in milliseconds for this device, replaces `--debounce-duration`
+    pub debounce: Option<u64>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct MirrorConfig {
+    pub topic: String,
+    /// payload published when the camera turns on, `{state}`, `{device}` and `{process}` get
+    /// filled in
+    pub payload_on: String,
+    /// payload template published when the camera turns off
+    pub payload_off: String,
+    #[serde(default)]
+    pub retain: bool,
+}
+
+/// raise an alarm when something not in `expected_processes` opens a camera
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct SecurityConfig {
+    pub enabled: bool,
+    /// url to POST the process details to
+    pub webhook: Option<String>,
+}
+
+/// share our state with the other instances on the broker and publish whether any of them has a
+/// camera on
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct PeersConfig {
+    /// topic prefix all the instances share, peering is off without one
+    pub prefix: Option<String>,
+    /// what this instance goes by under the prefix, defaults to the hostname
+    pub name: Option<String>,
+}
+
+/// templates for the topics we publish on, in place of the `homeassistant/...` defaults. see
+/// `mqtt::Topics` for the placeholders
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct TopicsConfig {
+    /// what goes between the component and the object id in the discovery topics, and in front
+    /// of the unique ids, `officecamera` by default. only gets `{hostname}`
+    pub node_id: Option<String>,
+    pub state: Option<String>,
+    pub attributes: Option<String>,
+    pub availability: Option<String>,
+    pub security_alert: Option<String>,
+    pub event: Option<String>,
+    pub trigger: Option<String>,
+    pub session_duration: Option<String>,
+    pub application: Option<String>,
+    /// one topic per usage counter, so this needs `{object_id}`
+    pub stats: Option<String>,
+    /// the per-camera ones need `{device}` or `{object_id}`
+    pub device_state: Option<String>,
+    pub device_availability: Option<String>,
+    pub last_used: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertService {
+    Ntfy,
+    Pushover,
+    Telegram,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+#[cfg_attr(not(feature = "alerts"), allow(dead_code))]
+pub struct AlertConfig {
+    pub service: AlertService,
+    /// topic url, for ntfy
+    pub url: Option<String>,
+    /// access token for ntfy, app token for pushover, bot token for telegram
+    pub token: Option<String>,
+    /// user key, for pushover
+    pub user: Option<String>,
+    /// chat to post to, for telegram
+    pub chat_id: Option<String>,
+    /// only alert when something not in `expected_processes` uses the camera
+    #[serde(default)]
+    pub unexpected_only: bool,
+    /// don't alert inside this window, e.g. `09:00-17:00` for work hours
+    pub outside_hours: Option<crate::schedule::Window>,
+    /// minimum seconds between two alerts on this channel
+    #[serde(default = "default_min_interval")]
+    pub min_interval: u64,
+}
+
+fn default_min_interval() -> u64 {
+    60
+}
+
+impl Config {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("reading config file {}", path.display()))?;
+
+        toml::from_str(&contents).with_context(|| format!("parsing config file {}", path.display()))
+    }
+
+    pub fn device(&self, device: &Path) -> Option<&DeviceConfig> {
+        self.devices.get(device)
+    }
+
+    /// the devices with their own debounce window
+    pub fn debounce_windows(&self) -> HashMap<PathBuf, Duration> {
+        self.devices
+            .iter()
+            .filter_map(|(path, device)| {
+                Some((path.clone(), Duration::from_millis(device.debounce?)))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,156 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::{mpsc, watch};
+
+use crate::monitor::{DeviceEvent, DeviceEventKind};
+use crate::status::Status;
+
+/// one line of JSON sent to the control socket
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Request {
+    /// pretend `device` was opened, closed, plugged in or unplugged, it goes through debouncing
+    /// like a real event
+    Simulate {
+        device: PathBuf,
+        kind: DeviceEventKind,
+    },
+    /// what the daemon last published
+    Status,
+}
+
+/// the line of JSON sent back for every request
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Response {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<Status>,
+}
+
+/// listens on `path` for requests, simulated events get fed into `events` next to the real ones
+///
+/// `events` is weak so the socket doesn't keep the event loop going once the monitor is gone
+/// status requests get answered from whatever is current in `status`
+pub fn listen(
+    path: &Path,
+    events: mpsc::WeakSender<DeviceEvent>,
+    status: watch::Receiver<Status>,
+) -> anyhow::Result<()> {
+    // a stale socket from a previous run would make the bind fail
+    if path.exists() {
+        std::fs::remove_file(path)
+            .with_context(|| format!("removing stale control socket {}", path.display()))?;
+    }
+    let listener = UnixListener::bind(path)
+        .with_context(|| format!("binding control socket {}", path.display()))?;
+    tracing::info!("listening on control socket {}", path.display());
+
+    tokio::spawn(async move {
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    tracing::error!("error accepting control connection: {}", e);
+                    continue;
+                }
+            };
+
+            let events = events.clone();
+            let status = status.clone();
+            tokio::spawn(async move {
+                if let Err(e) = serve(stream, events, status).await {
+                    tracing::warn!("control connection failed: {}", e);
+                }
+            });
+        }
+    });
+
+    Ok(())
+}
+
+async fn serve(
+    stream: UnixStream,
+    events: mpsc::WeakSender<DeviceEvent>,
+    status: watch::Receiver<Status>,
+) -> anyhow::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    while let Some(line) = lines.next_line().await? {
+        let response = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => {
+                tracing::info!("control request: {:?}", request);
+                handle(request, &events, &status).await
+            }
+            Err(e) => Response {
+                error: Some(format!("invalid request: {}", e)),
+                status: None,
+            },
+        };
+
+        let mut line = serde_json::to_vec(&response)?;
+        line.push(b'\n');
+        writer.write_all(&line).await?;
+    }
+
+    Ok(())
+}
+
+async fn handle(
+    request: Request,
+    events: &mpsc::WeakSender<DeviceEvent>,
+    status: &watch::Receiver<Status>,
+) -> Response {
+    match request {
+        Request::Simulate { device, kind } => {
+            let event = DeviceEvent {
+                device,
+                kind,
+                process: None,
+            };
+            let sent = match events.upgrade() {
+                Some(events) => events.send(event).await.is_ok(),
+                None => false,
+            };
+            let error = (!sent).then(|| "daemon is shutting down".to_string());
+
+            Response {
+                error,
+                status: None,
+            }
+        }
+        Request::Status => Response {
+            error: None,
+            status: Some(status.borrow().clone()),
+        },
+    }
+}
+
+/// sends one request to the daemon listening on `path` and waits for its response
+pub async fn send(path: &Path, request: &Request) -> anyhow::Result<Response> {
+    let stream = UnixStream::connect(path)
+        .await
+        .with_context(|| format!("connecting to control socket {}", path.display()))?;
+    let (reader, mut writer) = stream.into_split();
+
+    let mut line = serde_json::to_vec(request)?;
+    line.push(b'\n');
+    writer.write_all(&line).await?;
+
+    let line = BufReader::new(reader)
+        .lines()
+        .next_line()
+        .await?
+        .context("control socket closed without a response")?;
+    let response: Response = serde_json::from_str(&line)?;
+
+    match response.error {
+        Some(error) => anyhow::bail!("{}", error),
+        None => Ok(response),
+    }
+}
@@ -0,0 +1,238 @@
+use std::collections::HashMap;
+
+use zbus::zvariant::Value;
+
+use crate::process::ProcessInfo;
+use crate::CameraState;
+
+// https://specifications.freedesktop.org/notification-spec/latest/protocol.html
+#[zbus::proxy(
+    interface = "org.freedesktop.Notifications",
+    default_service = "org.freedesktop.Notifications",
+    default_path = "/org/freedesktop/Notifications"
+)]
+trait Notifications {
+    #[allow(clippy::too_many_arguments)]
+    fn notify(
+        &self,
+        app_name: &str,
+        replaces_id: u32,
+        app_icon: &str,
+        summary: &str,
+        body: &str,
+        actions: &[&str],
+        hints: HashMap<&str, Value<'_>>,
+        expire_timeout: i32,
+    ) -> zbus::Result<u32>;
+}
+
+// https://www.freedesktop.org/software/systemd/man/latest/org.freedesktop.login1.html
+#[cfg(target_os = "linux")]
+#[zbus::proxy(
+    interface = "org.freedesktop.login1.Manager",
+    default_service = "org.freedesktop.login1",
+    default_path = "/org/freedesktop/login1"
+)]
+trait Login1Manager {
+    /// `start` is true right before going to sleep and false once the machine is back
+    #[zbus(signal)]
+    fn prepare_for_sleep(&self, start: bool) -> zbus::Result<()>;
+}
+
+/// sends on `resumed` every time logind says the machine woke up, until the system bus goes away
+#[cfg(target_os = "linux")]
+pub async fn watch_resume(
+    resumed: &tokio::sync::watch::Sender<&'static str>,
+) -> anyhow::Result<()> {
+    use futures_util::StreamExt;
+
+    let connection = zbus::Connection::system().await?;
+    let proxy = Login1ManagerProxy::new(&connection).await?;
+    let mut signals = proxy.receive_prepare_for_sleep().await?;
+
+    while let Some(signal) = signals.next().await {
+        if !signal.args()?.start {
+            tracing::info!("resumed from suspend");
+            resumed.send_replace("a resume");
+        }
+    }
+
+    Ok(())
+}
+
+/// sends freedesktop desktop notifications on state changes
+pub struct Notifier {
+    proxy: NotificationsProxy<'static>,
+    /// id of the last notification we sent, so the OFF notification replaces the ON one
+    last_id: u32,
+}
+
+impl Notifier {
+    /// connects to the session bus, this fails when running as a system service without one
+    pub async fn connect() -> anyhow::Result<Self> {
+        let connection = zbus::Connection::session().await?;
+        let proxy = NotificationsProxy::new(&connection).await?;
+
+        Ok(Self { proxy, last_id: 0 })
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn notify(&mut self, state: &CameraState, used_by: Option<&str>) {
+        let (summary, icon) = match state {
+            CameraState::On => ("Camera turned ON", "camera-on"),
+            CameraState::Off => ("Camera turned OFF", "camera-off"),
+        };
+        let body = used_by
+            .map(|name| format!("used by {}", name))
+            .unwrap_or_default();
+
+        let mut hints = HashMap::new();
+        // 2 = critical, 0 = low, the ON notification should stick around until it's seen
+        let urgency: u8 = match state {
+            CameraState::On => 2,
+            CameraState::Off => 0,
+        };
+        hints.insert("urgency", Value::from(urgency));
+
+        match self
+            .proxy
+            .notify(
+                "camera-snitch",
+                self.last_id,
+                icon,
+                summary,
+                &body,
+                &[],
+                hints,
+                -1,
+            )
+            .await
+        {
+            Ok(id) => self.last_id = id,
+            Err(e) => tracing::error!("error sending desktop notification: {}", e),
+        }
+    }
+
+    /// a separate critical notification for a process that isn't expected to use the camera,
+    /// it doesn't replace the state ones and doesn't time out
+    #[tracing::instrument(skip(self))]
+    pub async fn notify_security(&mut self, process: &ProcessInfo) {
+        let mut body = format!(
+            "{} (pid {}) opened {}",
+            process.display_name(),
+            process.pid,
+            process.device.display()
+        );
+        if let Some(exe) = &process.exe {
+            body.push_str(&format!("\n{}", exe.display()));
+        }
+
+        let mut hints = HashMap::new();
+        hints.insert("urgency", Value::from(2u8));
+
+        if let Err(e) = self
+            .proxy
+            .notify(
+                "camera-snitch",
+                0,
+                "security-high",
+                "Unexpected camera access",
+                &body,
+                &[],
+                hints,
+                0,
+            )
+            .await
+        {
+            tracing::error!("error sending desktop notification: {}", e);
+        }
+    }
+}
+
+/// which message bus to register the service on
+#[derive(clap::ValueEnum, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Bus {
+    Session,
+    System,
+}
+
+const SERVICE_NAME: &str = "dev.wseaton.CameraSnitch";
+const OBJECT_PATH: &str = "/dev/wseaton/CameraSnitch";
+
+/// the object served at `/dev/wseaton/CameraSnitch`
+struct CameraSnitch {
+    state: CameraState,
+}
+
+#[zbus::interface(name = "dev.wseaton.CameraSnitch")]
+impl CameraSnitch {
+    /// current state, "ON" or "OFF"
+    fn get_state(&self) -> &str {
+        self.state.as_payload()
+    }
+
+    /// emitted on every debounced state change, `used_by` is empty when unknown
+    #[zbus(signal)]
+    async fn state_changed(
+        emitter: &zbus::object_server::SignalEmitter<'_>,
+        state: &str,
+        used_by: &str,
+    ) -> zbus::Result<()>;
+}
+
+/// the `dev.wseaton.CameraSnitch` D-Bus service, other desktop components can call `GetState` or
+/// listen for `StateChanged` signals instead of going through MQTT
+pub struct Service {
+    connection: zbus::Connection,
+}
+
+impl Service {
+    pub async fn start(bus: Bus) -> anyhow::Result<Self> {
+        let builder = match bus {
+            Bus::Session => zbus::connection::Builder::session()?,
+            Bus::System => zbus::connection::Builder::system()?,
+        };
+
+        let connection = builder
+            .name(SERVICE_NAME)?
+            .serve_at(
+                OBJECT_PATH,
+                CameraSnitch {
+                    state: CameraState::Off,
+                },
+            )?
+            .build()
+            .await?;
+
+        tracing::info!(
+            "registered D-Bus service {} on the {:?} bus",
+            SERVICE_NAME,
+            bus
+        );
+
+        Ok(Self { connection })
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn set_state(&self, state: &CameraState, used_by: Option<&str>) {
+        if let Err(e) = self.update(state, used_by).await {
+            tracing::error!("error updating D-Bus service state: {}", e);
+        }
+    }
+
+    async fn update(&self, state: &CameraState, used_by: Option<&str>) -> zbus::Result<()> {
+        let iface = self
+            .connection
+            .object_server()
+            .interface::<_, CameraSnitch>(OBJECT_PATH)
+            .await?;
+
+        iface.get_mut().await.state = state.clone();
+        CameraSnitch::state_changed(
+            iface.signal_emitter(),
+            state.as_payload(),
+            used_by.unwrap_or_default(),
+        )
+        .await
+    }
+}
@@ -0,0 +1,428 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use tokio::time::{Duration, Instant};
+
+use crate::CameraState;
+
+/// a state change that made it through the debounce and is ready to publish
+#[derive(Debug, Clone)]
+pub struct Change {
+    pub state: CameraState,
+    pub device: PathBuf,
+    /// when the first event asking for this state came in, the change's latency is from here
+    pub observed: Instant,
+}
+
+/// what happened to an observed state
+#[derive(Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// nothing to do, the state matches what's published or a delay is still running
+    Unchanged,
+    /// the change landed inside the debounce window, it gets published once the window closes
+    /// unless the device goes back before then
+    Deferred,
+    /// the change should be published right away
+    Ready,
+}
+
+/// decides which device events turn into published state changes
+///
+/// every device is debounced on its own and the published state is on while any of them is.
+/// three things hold a device's change back: the debounce window after its last change, since
+/// cameras open and close a few times when they start up, the on/off delays, which need the new
+/// state to hold for that long, and the minimum interval between publishes, which keeps a camera
+/// that really is toggling every second from spamming whatever listens. either way the latest
+/// state is what gets published once they're up, so the sensor always ends up matching the devices
+pub struct Debouncer {
+    window: Duration,
+    /// per-device windows from the config, in place of `window`
+    windows: HashMap<PathBuf, Duration>,
+    on_delay: Duration,
+    off_delay: Duration,
+    /// least time between two published changes, whatever the debounce windows
+    min_interval: Duration,
+    devices: HashMap<PathBuf, DeviceDebounce>,
+    /// the combined state as last published
+    published: CameraState,
+    last_publish: Option<Instant>,
+    /// the device change that took the combined state away from `published`, waiting on the
+    /// minimum interval
+    held: Option<Change>,
+}
+
+/// where one device is at
+#[derive(Default)]
+struct DeviceDebounce {
+    /// the state its changes have settled on so far
+    state: CameraState,
+    last_change: Option<Instant>,
+    /// a change waiting on its delay or window, and when it's due
+    pending: Option<(Change, Instant)>,
+}
+
+impl Debouncer {
+    pub fn new(
+        window: Duration,
+        windows: HashMap<PathBuf, Duration>,
+        on_delay: Duration,
+        off_delay: Duration,
+        min_interval: Duration,
+    ) -> Self {
+        Self {
+            window,
+            windows,
+            on_delay,
+            off_delay,
+            min_interval,
+            devices: HashMap::new(),
+            published: CameraState::Off,
+            last_publish: None,
+            held: None,
+        }
+    }
+
+    fn window(&self, device: &Path) -> Duration {
+        self.windows.get(device).copied().unwrap_or(self.window)
+    }
+
+    /// when the minimum interval since the last publish is up, if it isn't yet
+    fn interval_end(&self, now: Instant) -> Option<Instant> {
+        self.last_publish
+            .map(|at| at + self.min_interval)
+            .filter(|end| *end > now)
+    }
+
+    /// feeds in the state a device event implies for `device`, `published` is the combined state
+    /// that's currently out there
+    ///
+    /// on `Ready` the change is waiting in `take`
+    pub fn observe(
+        &mut self,
+        state: CameraState,
+        device: &Path,
+        published: &CameraState,
+    ) -> Outcome {
+        self.published = published.clone();
+        let now = Instant::now();
+        let window = self.window(device);
+        let interval_end = self.interval_end(now);
+        let debounce = self.devices.entry(device.to_path_buf()).or_default();
+
+        if state == debounce.state {
+            // flapped back before the delay was up
+            if debounce.pending.take().is_some() {
+                tracing::debug!(
+                    "dropped pending change, {:?} is back to {:?}",
+                    device,
+                    state
+                );
+            }
+            return Outcome::Unchanged;
+        }
+
+        let window_end = debounce
+            .last_change
+            .map(|at| at + window)
+            .filter(|end| *end > now)
+            .max(interval_end);
+        let outcome = |delay: Duration| match window_end {
+            Some(_) => Outcome::Deferred,
+            None if delay.is_zero() => Outcome::Ready,
+            None => Outcome::Unchanged,
+        };
+
+        let delay = match state {
+            CameraState::On => self.on_delay,
+            CameraState::Off => self.off_delay,
+        };
+
+        // a change already on its way keeps its deadline
+        if debounce
+            .pending
+            .as_ref()
+            .is_some_and(|(c, _)| c.state == state)
+        {
+            return match outcome(delay) {
+                Outcome::Ready => Outcome::Unchanged,
+                outcome => outcome,
+            };
+        }
+
+        let due = match window_end {
+            Some(end) => end.max(now + delay),
+            None => now + delay,
+        };
+        let change = Change {
+            state,
+            device: device.to_path_buf(),
+            observed: now,
+        };
+        debounce.pending = Some((change, due));
+
+        outcome(delay)
+    }
+
+    /// when the next pending change is due, if there is one
+    pub fn deadline(&self) -> Option<Instant> {
+        let pending = self
+            .devices
+            .values()
+            .filter_map(|debounce| debounce.pending.as_ref().map(|(_, at)| *at));
+        let held = self
+            .last_publish
+            .filter(|_| self.held.is_some())
+            .map(|at| at + self.min_interval);
+
+        pending.chain(held).min()
+    }
+
+    /// settles the device changes that are due and hands out the combined change if that moves
+    /// it, starting the devices' debounce windows. call this once the deadline is up
+    pub fn take(&mut self) -> Option<Change> {
+        let now = Instant::now();
+        let mut due: Vec<(Change, Instant)> = self
+            .devices
+            .values_mut()
+            .filter(|debounce| debounce.pending.as_ref().is_some_and(|(_, at)| *at <= now))
+            .filter_map(|debounce| debounce.pending.take())
+            .collect();
+        due.sort_by_key(|(_, at)| *at);
+
+        for (change, _) in due {
+            let debounce = self.devices.entry(change.device.clone()).or_default();
+            debounce.state = change.state.clone();
+            debounce.last_change = Some(now);
+
+            let combined = self.combined();
+            if combined == self.published {
+                self.held = None;
+            } else if self.held.as_ref().is_none_or(|held| held.state != combined) {
+                self.held = Some(change);
+            }
+        }
+
+        if self.interval_end(now).is_some() {
+            return None;
+        }
+        let change = self.held.take()?;
+        self.published = change.state.clone();
+        self.last_publish = Some(now);
+
+        Some(change)
+    }
+
+    /// on while any device has settled on on
+    fn combined(&self) -> CameraState {
+        match self
+            .devices
+            .values()
+            .any(|debounce| debounce.state == CameraState::On)
+        {
+            true => CameraState::On,
+            false => CameraState::Off,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOW: Duration = Duration::from_secs(5);
+
+    fn debouncer(on_delay: u64, off_delay: u64) -> Debouncer {
+        Debouncer::new(
+            WINDOW,
+            HashMap::new(),
+            Duration::from_secs(on_delay),
+            Duration::from_secs(off_delay),
+            Duration::ZERO,
+        )
+    }
+
+    fn video0() -> &'static Path {
+        Path::new("/dev/video0")
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn first_change_is_ready_right_away() {
+        let mut debouncer = debouncer(0, 0);
+        let outcome = debouncer.observe(CameraState::On, video0(), &CameraState::Off);
+
+        assert_eq!(outcome, Outcome::Ready);
+        let change = debouncer.take().unwrap();
+        assert_eq!(change.state, CameraState::On);
+        assert_eq!(change.device, video0());
+        assert!(debouncer.take().is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn change_inside_the_window_waits_for_it_to_close() {
+        let mut debouncer = debouncer(0, 0);
+        debouncer.observe(CameraState::On, video0(), &CameraState::Off);
+        let published_at = Instant::now();
+        debouncer.take().unwrap();
+
+        tokio::time::advance(Duration::from_secs(1)).await;
+        let outcome = debouncer.observe(CameraState::Off, video0(), &CameraState::On);
+
+        assert_eq!(outcome, Outcome::Deferred);
+        assert_eq!(debouncer.deadline(), Some(published_at + WINDOW));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn going_back_inside_the_window_drops_the_change() {
+        let mut debouncer = debouncer(0, 0);
+        debouncer.observe(CameraState::On, video0(), &CameraState::Off);
+        debouncer.take().unwrap();
+
+        debouncer.observe(CameraState::Off, video0(), &CameraState::On);
+        let outcome = debouncer.observe(CameraState::On, video0(), &CameraState::On);
+
+        assert_eq!(outcome, Outcome::Unchanged);
+        assert_eq!(debouncer.deadline(), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn trailing_edge_publishes_the_latest_state() {
+        let mut debouncer = debouncer(0, 0);
+        debouncer.observe(CameraState::On, video0(), &CameraState::Off);
+        debouncer.take().unwrap();
+
+        // off, flaps back on, off again, all inside the window
+        debouncer.observe(CameraState::Off, video0(), &CameraState::On);
+        debouncer.observe(CameraState::On, video0(), &CameraState::On);
+        tokio::time::advance(Duration::from_secs(2)).await;
+        let last_off = Instant::now();
+        assert_eq!(
+            debouncer.observe(CameraState::Off, video0(), &CameraState::On),
+            Outcome::Deferred
+        );
+
+        tokio::time::advance(WINDOW).await;
+        let change = debouncer.take().unwrap();
+        assert_eq!(change.state, CameraState::Off);
+        assert_eq!(change.observed, last_off);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn on_delay_holds_the_change_and_keeps_its_deadline() {
+        let mut debouncer = debouncer(3, 0);
+        let start = Instant::now();
+
+        assert_eq!(
+            debouncer.observe(CameraState::On, video0(), &CameraState::Off),
+            Outcome::Unchanged
+        );
+        assert_eq!(debouncer.deadline(), Some(start + Duration::from_secs(3)));
+
+        // another open on the way doesn't push it back
+        tokio::time::advance(Duration::from_secs(2)).await;
+        debouncer.observe(CameraState::On, video0(), &CameraState::Off);
+        assert_eq!(debouncer.deadline(), Some(start + Duration::from_secs(3)));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn closing_before_the_on_delay_is_up_publishes_nothing() {
+        let mut debouncer = debouncer(3, 0);
+        debouncer.observe(CameraState::On, video0(), &CameraState::Off);
+
+        tokio::time::advance(Duration::from_secs(1)).await;
+        debouncer.observe(CameraState::Off, video0(), &CameraState::Off);
+
+        assert_eq!(debouncer.deadline(), None);
+        assert!(debouncer.take().is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn off_delay_only_applies_to_off() {
+        let mut debouncer = debouncer(0, 4);
+        assert_eq!(
+            debouncer.observe(CameraState::On, video0(), &CameraState::Off),
+            Outcome::Ready
+        );
+        debouncer.take().unwrap();
+
+        tokio::time::advance(WINDOW).await;
+        let closed = Instant::now();
+        assert_eq!(
+            debouncer.observe(CameraState::Off, video0(), &CameraState::On),
+            Outcome::Unchanged
+        );
+        assert_eq!(debouncer.deadline(), Some(closed + Duration::from_secs(4)));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn per_device_window_and_min_interval() {
+        let windows = HashMap::from([(PathBuf::from("/dev/video2"), Duration::from_secs(1))]);
+        let mut debouncer = Debouncer::new(
+            WINDOW,
+            windows,
+            Duration::ZERO,
+            Duration::ZERO,
+            Duration::from_secs(2),
+        );
+        let video2 = Path::new("/dev/video2");
+        debouncer.observe(CameraState::On, video2, &CameraState::Off);
+        let published_at = Instant::now();
+        debouncer.take().unwrap();
+
+        // its own window is a second, but changes can't come closer than two apart
+        debouncer.observe(CameraState::Off, video2, &CameraState::On);
+        assert_eq!(
+            debouncer.deadline(),
+            Some(published_at + Duration::from_secs(2))
+        );
+
+        // and the default window is still there for everything else
+        debouncer.observe(CameraState::On, video2, &CameraState::On);
+        assert_eq!(
+            debouncer.observe(CameraState::On, video0(), &CameraState::On),
+            Outcome::Deferred
+        );
+        tokio::time::advance(Duration::from_secs(2)).await;
+        assert!(debouncer.take().is_none());
+        let video0_at = Instant::now();
+        debouncer.observe(CameraState::Off, video0(), &CameraState::On);
+        assert_eq!(debouncer.deadline(), Some(video0_at + WINDOW));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn devices_keep_their_own_windows() {
+        let windows = HashMap::from([(PathBuf::from("/dev/video2"), Duration::from_secs(1))]);
+        let mut debouncer = Debouncer::new(
+            WINDOW,
+            windows,
+            Duration::ZERO,
+            Duration::ZERO,
+            Duration::ZERO,
+        );
+        let video2 = Path::new("/dev/video2");
+        let start = Instant::now();
+        debouncer.observe(CameraState::On, video0(), &CameraState::Off);
+        assert_eq!(debouncer.take().unwrap().state, CameraState::On);
+        // video0 already has it on, so video2 settling doesn't publish anything
+        assert_eq!(
+            debouncer.observe(CameraState::On, video2, &CameraState::On),
+            Outcome::Ready
+        );
+        assert!(debouncer.take().is_none());
+
+        // video2's close waits out its own second, not video0's five
+        tokio::time::advance(Duration::from_millis(500)).await;
+        debouncer.observe(CameraState::Off, video2, &CameraState::On);
+        assert_eq!(debouncer.deadline(), Some(start + Duration::from_secs(1)));
+        // and video0's close isn't cut short by video2's
+        debouncer.observe(CameraState::Off, video0(), &CameraState::On);
+
+        tokio::time::advance(Duration::from_millis(500)).await;
+        assert!(debouncer.take().is_none());
+        assert_eq!(debouncer.deadline(), Some(start + WINDOW));
+
+        tokio::time::advance(Duration::from_secs(4)).await;
+        let change = debouncer.take().unwrap();
+        assert_eq!(change.state, CameraState::Off);
+        assert_eq!(change.device, video0());
+    }
+}
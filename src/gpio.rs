@@ -0,0 +1,156 @@
+//! drives a GPIO line directly, for an on-air light or relay on a Raspberry Pi or similar board
+
+use std::fs::File;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::path::PathBuf;
+
+use anyhow::Context;
+
+use crate::CameraState;
+
+#[derive(clap::Args, Debug)]
+pub struct GpioArgs {
+    /// GPIO line to drive high while the camera is on, by its offset on `--gpio-chip`
+    #[clap(long)]
+    gpio_pin: Option<u32>,
+    /// drive the line low while the camera is on instead, for active-low relay boards
+    #[clap(long, requires = "gpio_pin")]
+    gpio_active_low: bool,
+    /// GPIO character device the pin belongs to
+    #[clap(long, default_value = "/dev/gpiochip0")]
+    gpio_chip: PathBuf,
+}
+
+// the v2 character device uAPI from linux/gpio.h, sysfs GPIO is on its way out
+
+const GPIO_V2_LINES_MAX: usize = 64;
+const GPIO_V2_LINE_NUM_ATTRS_MAX: usize = 10;
+const GPIO_V2_LINE_FLAG_ACTIVE_LOW: u64 = 1 << 1;
+const GPIO_V2_LINE_FLAG_OUTPUT: u64 = 1 << 3;
+const GPIO_V2_LINE_ATTR_ID_OUTPUT_VALUES: u32 = 2;
+
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+struct LineAttribute {
+    id: u32,
+    padding: u32,
+    /// flags, values or debounce period depending on `id`, a union in the header
+    value: u64,
+}
+
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+struct LineConfigAttribute {
+    attr: LineAttribute,
+    mask: u64,
+}
+
+#[repr(C)]
+struct LineConfig {
+    flags: u64,
+    num_attrs: u32,
+    padding: [u32; 5],
+    attrs: [LineConfigAttribute; GPIO_V2_LINE_NUM_ATTRS_MAX],
+}
+
+#[repr(C)]
+struct LineRequest {
+    offsets: [u32; GPIO_V2_LINES_MAX],
+    consumer: [u8; 32],
+    config: LineConfig,
+    num_lines: u32,
+    event_buffer_size: u32,
+    padding: [u32; 5],
+    fd: i32,
+}
+
+#[repr(C)]
+struct LineValues {
+    bits: u64,
+    mask: u64,
+}
+
+// the ioctl numbers encode the struct sizes, so a layout mistake would make them unknown ioctls
+const _: () = assert!(std::mem::size_of::<LineRequest>() == 592);
+const _: () = assert!(std::mem::size_of::<LineValues>() == 16);
+
+nix::ioctl_readwrite!(get_line, 0xb4, 0x07, LineRequest);
+nix::ioctl_readwrite!(set_values, 0xb4, 0x0f, LineValues);
+
+/// an output line requested from the GPIO chip, released again when this is dropped
+pub struct Gpio {
+    line: OwnedFd,
+}
+
+impl Gpio {
+    /// requests the line as an output, initially off, `None` unless `--gpio-pin` was passed
+    pub fn from_args(args: &GpioArgs) -> anyhow::Result<Option<Self>> {
+        let Some(pin) = args.gpio_pin else {
+            return Ok(None);
+        };
+
+        let chip = File::open(&args.gpio_chip)
+            .with_context(|| format!("opening {}", args.gpio_chip.display()))?;
+
+        let mut flags = GPIO_V2_LINE_FLAG_OUTPUT;
+        if args.gpio_active_low {
+            // the kernel does the inverting, so on is always a logical 1
+            flags |= GPIO_V2_LINE_FLAG_ACTIVE_LOW;
+        }
+        let mut attrs = [LineConfigAttribute::default(); GPIO_V2_LINE_NUM_ATTRS_MAX];
+        attrs[0] = LineConfigAttribute {
+            attr: LineAttribute {
+                id: GPIO_V2_LINE_ATTR_ID_OUTPUT_VALUES,
+                padding: 0,
+                value: 0,
+            },
+            mask: 1,
+        };
+        let mut offsets = [0; GPIO_V2_LINES_MAX];
+        offsets[0] = pin;
+        let mut consumer = [0; 32];
+        consumer[..13].copy_from_slice(b"camera-snitch");
+
+        let mut request = LineRequest {
+            offsets,
+            consumer,
+            config: LineConfig {
+                flags,
+                num_attrs: 1,
+                padding: [0; 5],
+                attrs,
+            },
+            num_lines: 1,
+            event_buffer_size: 0,
+            padding: [0; 5],
+            fd: -1,
+        };
+        // SAFETY: `request` matches struct gpio_v2_line_request and outlives the call
+        unsafe { get_line(chip.as_raw_fd(), &mut request) }
+            .with_context(|| format!("requesting line {} on {}", pin, args.gpio_chip.display()))?;
+        tracing::info!("driving gpio line {} on {}", pin, args.gpio_chip.display());
+
+        // SAFETY: the kernel just handed us this fd and nothing else owns it
+        let line = unsafe { OwnedFd::from_raw_fd(request.fd) };
+
+        Ok(Some(Self { line }))
+    }
+
+    pub fn set_state(&self, state: &CameraState) {
+        let mut values = LineValues {
+            bits: (*state == CameraState::On) as u64,
+            mask: 1,
+        };
+        // SAFETY: `values` matches struct gpio_v2_line_values and outlives the call
+        if let Err(e) = unsafe { set_values(self.line.as_raw_fd(), &mut values) } {
+            tracing::error!("error setting gpio line: {}", e);
+        }
+    }
+}
+
+impl Drop for Gpio {
+    /// leave the light off rather than whatever it was when we stopped
+    fn drop(&mut self) {
+        self.set_state(&CameraState::Off);
+    }
+}
@@ -0,0 +1,253 @@
+//! the gRPC API from `proto/camera_snitch.proto`, `GetState` answers from the daemon's status and
+//! `WatchEvents` streams its events
+
+use std::net::SocketAddr;
+use std::pin::Pin;
+
+use anyhow::Context;
+use futures_util::Stream;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::watch;
+use tonic::{Request, Response};
+
+use crate::status::{Event, EventKind, Status};
+use crate::CameraState;
+
+mod proto {
+    tonic::include_proto!("camera_snitch.v1");
+}
+
+use proto::camera_event::Kind;
+use proto::camera_snitch_server::{CameraSnitch, CameraSnitchServer};
+
+/// starts serving the gRPC API in the background, `events` being a receiver on the daemon's events
+/// that every watcher gets a copy of
+pub async fn serve(
+    addr: SocketAddr,
+    status: watch::Receiver<Status>,
+    events: broadcast::Receiver<Event>,
+) -> anyhow::Result<()> {
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("binding grpc server to {}", addr))?;
+    let incoming = tonic::transport::server::TcpIncoming::from_listener(listener, true, None)
+        .map_err(|e| anyhow::anyhow!("grpc listener on {}: {}", addr, e))?;
+    tracing::info!("serving grpc on {}", addr);
+
+    let service = CameraSnitchServer::new(Service { status, events });
+    tokio::spawn(async move {
+        let server = tonic::transport::Server::builder()
+            .add_service(service)
+            .serve_with_incoming(incoming);
+        if let Err(e) = server.await {
+            tracing::error!("grpc server stopped: {}", e);
+        }
+    });
+
+    Ok(())
+}
+
+struct Service {
+    status: watch::Receiver<Status>,
+    /// never read, it's only here to `resubscribe` from, which unlike a sender doesn't keep the
+    /// channel open once the daemon is done with it
+    events: broadcast::Receiver<Event>,
+}
+
+type EventStream = Pin<Box<dyn Stream<Item = Result<proto::CameraEvent, tonic::Status>> + Send>>;
+
+#[tonic::async_trait]
+impl CameraSnitch for Service {
+    async fn get_state(
+        &self,
+        _: Request<proto::GetStateRequest>,
+    ) -> Result<Response<proto::State>, tonic::Status> {
+        Ok(Response::new(state(&self.status.borrow())))
+    }
+
+    type WatchEventsStream = EventStream;
+
+    async fn watch_events(
+        &self,
+        _: Request<proto::WatchEventsRequest>,
+    ) -> Result<Response<EventStream>, tonic::Status> {
+        let events = self.events.resubscribe();
+        let stream = futures_util::stream::unfold(
+            (events, self.status.clone()),
+            |(mut events, status)| async move {
+                let event = next(&mut events, &status).await?;
+                Some((Ok(event), (events, status)))
+            },
+        );
+
+        Ok(Response::new(Box::pin(stream)))
+    }
+}
+
+/// the next thing to send a watcher, `None` once the daemon is gone
+async fn next(
+    events: &mut broadcast::Receiver<Event>,
+    status: &watch::Receiver<Status>,
+) -> Option<proto::CameraEvent> {
+    match events.recv().await {
+        Ok(event) => Some(camera_event(&event)),
+        Err(RecvError::Lagged(missed)) => {
+            tracing::warn!("grpc watcher fell behind, it missed {} events", missed);
+            Some(resync(&status.borrow(), missed))
+        }
+        Err(RecvError::Closed) => None,
+    }
+}
+
+fn camera_state(state: &CameraState) -> proto::CameraState {
+    match state {
+        CameraState::On => proto::CameraState::On,
+        CameraState::Off => proto::CameraState::Off,
+    }
+}
+
+/// the status as the API has it, with empty strings and zeroes for what's not known
+fn state(status: &Status) -> proto::State {
+    let since = status
+        .since
+        .as_deref()
+        .and_then(|since| chrono::DateTime::parse_from_rfc3339(since).ok())
+        .map(|since| since.timestamp())
+        .unwrap_or_default();
+    let devices = status
+        .devices
+        .iter()
+        .map(|(device, state)| proto::DeviceState {
+            device: device.display().to_string(),
+            state: camera_state(state).into(),
+        })
+        .collect();
+
+    proto::State {
+        state: camera_state(&status.state).into(),
+        device: status
+            .device
+            .as_ref()
+            .map(|device| device.display().to_string())
+            .unwrap_or_default(),
+        used_by: status.used_by.clone().unwrap_or_default(),
+        since,
+        devices,
+    }
+}
+
+fn camera_event(event: &Event) -> proto::CameraEvent {
+    let kind = match event.kind {
+        EventKind::DeviceOpened => Kind::DeviceOpened,
+        EventKind::DeviceClosed => Kind::DeviceClosed,
+        EventKind::StateChanged => Kind::StateChanged,
+    };
+
+    proto::CameraEvent {
+        kind: kind.into(),
+        device: event.device.display().to_string(),
+        state: camera_state(&event.state).into(),
+        used_by: event.used_by.clone().unwrap_or_default(),
+        timestamp: event.timestamp.timestamp_millis(),
+        missed: 0,
+    }
+}
+
+/// tells a watcher that fell behind where things are now
+fn resync(status: &Status, missed: u64) -> proto::CameraEvent {
+    let state = state(status);
+
+    proto::CameraEvent {
+        kind: Kind::Resync.into(),
+        device: state.device,
+        state: state.state,
+        used_by: state.used_by,
+        timestamp: chrono::Utc::now().timestamp_millis(),
+        missed,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn opened(device: &str) -> Event {
+        Event::new(
+            EventKind::DeviceOpened,
+            PathBuf::from(device),
+            CameraState::On,
+            Some("zoom".into()),
+        )
+    }
+
+    #[tokio::test]
+    async fn every_event_gets_through() {
+        let (tx, mut events) = broadcast::channel(8);
+        let (_status_tx, status) = watch::channel(Status::default());
+
+        tx.send(opened("/dev/video0")).unwrap();
+        tx.send(Event::new(
+            EventKind::DeviceClosed,
+            PathBuf::from("/dev/video0"),
+            CameraState::Off,
+            None,
+        ))
+        .unwrap();
+        drop(tx);
+
+        let first = next(&mut events, &status).await.unwrap();
+        assert_eq!(first.kind(), Kind::DeviceOpened);
+        assert_eq!(first.device, "/dev/video0");
+        assert_eq!(first.state(), proto::CameraState::On);
+        assert_eq!(first.used_by, "zoom");
+        let second = next(&mut events, &status).await.unwrap();
+        assert_eq!(second.kind(), Kind::DeviceClosed);
+        assert_eq!(second.state(), proto::CameraState::Off);
+        assert!(next(&mut events, &status).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn falling_behind_resyncs() {
+        let (tx, mut events) = broadcast::channel(2);
+        let (_status_tx, status) = watch::channel(Status {
+            state: CameraState::On,
+            device: Some(PathBuf::from("/dev/video2")),
+            used_by: Some("obs".into()),
+            ..Default::default()
+        });
+
+        for device in ["/dev/video0", "/dev/video1", "/dev/video2"] {
+            tx.send(opened(device)).unwrap();
+        }
+
+        let resync = next(&mut events, &status).await.unwrap();
+        assert_eq!(resync.kind(), Kind::Resync);
+        assert_eq!(resync.missed, 1);
+        assert_eq!(resync.state(), proto::CameraState::On);
+        assert_eq!(resync.device, "/dev/video2");
+        assert_eq!(resync.used_by, "obs");
+        // and it carries on with what's still there
+        assert_eq!(
+            next(&mut events, &status).await.unwrap().device,
+            "/dev/video1"
+        );
+    }
+
+    #[test]
+    fn state_takes_since_as_unix_seconds() {
+        let status = Status {
+            state: CameraState::On,
+            since: Some("2024-05-14T10:00:00+02:00".into()),
+            devices: [(PathBuf::from("/dev/video0"), CameraState::On)].into(),
+            ..Default::default()
+        };
+
+        let state = state(&status);
+        assert_eq!(state.state(), proto::CameraState::On);
+        assert_eq!(state.since, 1715673600);
+        assert_eq!(state.devices.len(), 1);
+        assert_eq!(state.used_by, "");
+    }
+}
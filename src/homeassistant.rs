@@ -0,0 +1,94 @@
+use std::time::Duration;
+
+use tokio::sync::watch;
+
+use crate::CameraState;
+
+const ENTITY_ID: &str = "binary_sensor.officecamera";
+
+/// how often the state gets pushed again when nothing changed, states set through the REST API
+/// only last until HA restarts
+const REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+/// how long to wait before trying again after a failed push
+const RETRY_INTERVAL: Duration = Duration::from_secs(10);
+
+/// pushes the camera state straight into home assistant through its REST API, for setups without
+/// an MQTT broker
+///
+/// requests go out from a background task that always sends the latest state, so a slow or
+/// unreachable HA can't hold up the main loop or get updates out of order
+pub struct RestClient {
+    tx: watch::Sender<(CameraState, Option<String>)>,
+}
+
+impl RestClient {
+    /// `url` is the base url of the HA instance, e.g. `http://homeassistant.local:8123`, `token` a
+    /// long-lived access token
+    pub fn start(url: &str, token: String) -> anyhow::Result<Self> {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()?;
+        let endpoint = format!("{}/api/states/{}", url.trim_end_matches('/'), ENTITY_ID);
+        let (tx, mut rx) = watch::channel((CameraState::Off, None));
+
+        tokio::spawn(async move {
+            loop {
+                let (state, used_by) = rx.borrow_and_update().clone();
+                let wait = match push(&http, &endpoint, &token, &state, used_by.as_deref()).await {
+                    Ok(()) => REFRESH_INTERVAL,
+                    Err(e) => {
+                        tracing::warn!("error updating home assistant, retrying: {}", e);
+                        RETRY_INTERVAL
+                    }
+                };
+
+                tokio::select! {
+                    changed = rx.changed() => {
+                        if changed.is_err() {
+                            return;
+                        }
+                    }
+                    _ = tokio::time::sleep(wait) => {}
+                }
+            }
+        });
+
+        Ok(Self { tx })
+    }
+
+    pub fn set_state(&self, state: &CameraState, used_by: Option<&str>) {
+        self.tx
+            .send_replace((state.clone(), used_by.map(str::to_string)));
+    }
+}
+
+#[tracing::instrument(skip(http, token))]
+async fn push(
+    http: &reqwest::Client,
+    endpoint: &str,
+    token: &str,
+    state: &CameraState,
+    used_by: Option<&str>,
+) -> anyhow::Result<()> {
+    let body = serde_json::json!({
+        "state": match state {
+            CameraState::On => "on",
+            CameraState::Off => "off",
+        },
+        "attributes": {
+            "friendly_name": "Office Camera",
+            "device_class": "connectivity",
+            "application": used_by,
+        },
+    });
+
+    http.post(endpoint)
+        .bearer_auth(token)
+        .json(&body)
+        .send()
+        .await?
+        .error_for_status()?;
+    tracing::debug!("updated home assistant: {}", state.as_payload());
+
+    Ok(())
+}
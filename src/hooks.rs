@@ -0,0 +1,57 @@
+use std::path::Path;
+
+use crate::process::ProcessInfo;
+use crate::CameraState;
+
+/// spawns `command` through `sh -c` (`cmd /C` on windows) with the details of the state change in its environment
+///
+/// the hook runs in the background so a slow script can't hold up publishing, its exit status is
+/// only logged
+///
+/// - `CAMERA_STATE`: `ON` or `OFF`
+/// - `CAMERA_DEVICE`: the device that triggered the change, e.g. `/dev/video0`
+/// - `CAMERA_PROCESS`: comma separated names of the processes using a camera, if known
+/// - `CAMERA_PID`: comma separated pids of those processes
+#[tracing::instrument(skip(openers))]
+pub fn run(command: &str, state: &CameraState, device: Option<&Path>, openers: &[ProcessInfo]) {
+    let processes = crate::process::describe(openers).unwrap_or_default();
+    let pids = openers
+        .iter()
+        .map(|p| p.pid.to_string())
+        .collect::<Vec<_>>()
+        .join(",");
+
+    #[cfg(not(windows))]
+    let mut cmd = tokio::process::Command::new("sh");
+    #[cfg(not(windows))]
+    cmd.arg("-c");
+    #[cfg(windows)]
+    let mut cmd = tokio::process::Command::new("cmd");
+    #[cfg(windows)]
+    cmd.arg("/C");
+
+    cmd.arg(command)
+        .env("CAMERA_STATE", state.as_payload())
+        .env("CAMERA_PROCESS", processes)
+        .env("CAMERA_PID", pids);
+    if let Some(device) = device {
+        cmd.env("CAMERA_DEVICE", device);
+    }
+
+    let mut child = match cmd.spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            tracing::error!("error spawning hook: {}", e);
+            return;
+        }
+    };
+
+    let command = command.to_string();
+    tokio::spawn(async move {
+        match child.wait().await {
+            Ok(status) if status.success() => tracing::debug!("hook `{}` finished", command),
+            Ok(status) => tracing::warn!("hook `{}` exited with {}", command, status),
+            Err(e) => tracing::error!("error waiting for hook `{}`: {}", command, e),
+        }
+    });
+}
@@ -0,0 +1,34 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// this machine's hostname, for telling instances apart on a shared broker
+pub fn hostname() -> String {
+    #[cfg(unix)]
+    let hostname = nix::unistd::gethostname()
+        .ok()
+        .and_then(|name| name.into_string().ok());
+    #[cfg(windows)]
+    let hostname = std::env::var("COMPUTERNAME").ok();
+
+    hostname.unwrap_or_else(|| "localhost".to_string())
+}
+
+/// `bytes` random bytes as hex. `RandomState` is randomly keyed each time, which is plenty for ids
+/// and saves pulling in a rng
+pub fn random_id(bytes: usize) -> String {
+    let mut id = String::with_capacity(bytes * 2 + 16);
+    while id.len() < bytes * 2 {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_nanos() as u64)
+                .unwrap_or_default(),
+        );
+        id.push_str(&format!("{:016x}", hasher.finish()));
+    }
+    id.truncate(bytes * 2);
+
+    id
+}
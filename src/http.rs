@@ -0,0 +1,150 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Instant;
+
+use anyhow::Context;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::Response;
+use axum::routing::get;
+use axum::{Json, Router};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::watch;
+
+use crate::status::{Event, Status};
+
+#[derive(Clone)]
+struct AppState {
+    status: watch::Receiver<Status>,
+    /// only here to `resubscribe` from, one per socket
+    events: Arc<broadcast::Receiver<Event>>,
+    started: Instant,
+}
+
+/// serves `/state`, `/healthz` and the `/ws` stream on `addr`, for dashboards and uptime checks
+/// that would rather not speak MQTT
+pub async fn serve(
+    addr: SocketAddr,
+    status: watch::Receiver<Status>,
+    events: broadcast::Receiver<Event>,
+) -> anyhow::Result<()> {
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("binding http server to {}", addr))?;
+    tracing::info!("serving http on {}", addr);
+
+    let app = Router::new()
+        .route("/state", get(state))
+        .route("/healthz", get(healthz))
+        .route("/ws", get(ws))
+        .with_state(AppState {
+            status,
+            events: Arc::new(events),
+            started: Instant::now(),
+        });
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, app).await {
+            tracing::error!("http server stopped: {}", e);
+        }
+    });
+
+    Ok(())
+}
+
+async fn state(State(app): State<AppState>) -> Json<Status> {
+    Json(app.status.borrow().clone())
+}
+
+/// 200 while things are working, 503 while the broker is unreachable
+async fn healthz(State(app): State<AppState>) -> (StatusCode, Json<serde_json::Value>) {
+    let mqtt_connected = app.status.borrow().mqtt_connected;
+    let code = match mqtt_connected {
+        Some(false) => StatusCode::SERVICE_UNAVAILABLE,
+        _ => StatusCode::OK,
+    };
+
+    let features = [
+        ("inotify", cfg!(feature = "inotify")),
+        ("poll", cfg!(feature = "poll")),
+        ("ebpf", cfg!(feature = "ebpf")),
+        ("dbus", cfg!(feature = "dbus")),
+        ("sessions", cfg!(feature = "sessions")),
+        ("home-assistant", cfg!(feature = "home-assistant")),
+        ("alerts", cfg!(feature = "alerts")),
+        ("busylight", cfg!(feature = "busylight")),
+        ("grpc", cfg!(feature = "grpc")),
+        ("otel", cfg!(feature = "otel")),
+        ("testing", cfg!(feature = "testing")),
+    ];
+    let body = serde_json::json!({
+        "status": if code == StatusCode::OK { "ok" } else { "degraded" },
+        "mqtt_connected": mqtt_connected,
+        "uptime": app.started.elapsed().as_secs(),
+        "build": {
+            "version": env!("CARGO_PKG_VERSION"),
+            "features": features
+                .into_iter()
+                .filter_map(|(name, enabled)| enabled.then_some(name))
+                .collect::<Vec<_>>(),
+        },
+    });
+
+    (code, Json(body))
+}
+
+/// upgrades to a WebSocket that gets the `/state` JSON right away, then every event as it happens
+async fn ws(State(app): State<AppState>, upgrade: WebSocketUpgrade) -> Response {
+    // subscribed before the state gets sent, so nothing happens in between unseen
+    let events = app.events.resubscribe();
+    upgrade.on_upgrade(move |socket| async move {
+        if let Err(e) = stream(socket, app.status, events).await {
+            tracing::debug!("websocket closed: {}", e);
+        }
+    })
+}
+
+/// the published state, tagged with `kind` so it can be told apart from the events
+fn status_message(status: &Status, kind: &str) -> anyhow::Result<serde_json::Value> {
+    let mut json = serde_json::to_value(status)?;
+    if let Some(object) = json.as_object_mut() {
+        object.insert("kind".into(), kind.into());
+    }
+
+    Ok(json)
+}
+
+async fn stream(
+    mut socket: WebSocket,
+    status: watch::Receiver<Status>,
+    mut events: broadcast::Receiver<Event>,
+) -> anyhow::Result<()> {
+    let json = status_message(&status.borrow(), "status")?;
+    socket.send(Message::Text(json.to_string())).await?;
+
+    loop {
+        let json = tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) => serde_json::to_value(&event)?,
+                Err(RecvError::Lagged(missed)) => {
+                    tracing::warn!("websocket fell behind, it missed {} events", missed);
+                    let mut json = status_message(&status.borrow(), "resync")?;
+                    json["missed"] = missed.into();
+                    json
+                }
+                Err(RecvError::Closed) => {
+                    // shutting down
+                    socket.send(Message::Close(None)).await?;
+                    return Ok(());
+                }
+            },
+            message = socket.recv() => match message {
+                // pings get answered by axum as long as we keep reading
+                Some(Ok(Message::Close(_))) | None => return Ok(()),
+                Some(Ok(_)) => continue,
+                Some(Err(e)) => return Err(e.into()),
+            },
+        };
+        socket.send(Message::Text(json.to_string())).await?;
+    }
+}
@@ -0,0 +1,1665 @@
+//! camera-snitch: watches the cameras and reports when they turn on or off, to Home Assistant
+//! over MQTT or to whatever else is set up
+//!
+//! the `camera-notifier` binary is a thin wrapper around [`cli`]. the device monitors and the
+//! `/proc` lookups behind process attribution make sense on their own and are public, and with
+//! the `testing` feature so is [`testing::run`], which drives the whole daemon on a fake monitor
+//! and publisher
+
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+use tokio::time::Duration;
+
+use clap::{CommandFactory, FromArgMatches, Parser};
+use rumqttc::{Event, Incoming, MqttOptions};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::Layer;
+
+#[cfg(feature = "alerts")]
+mod alerts;
+mod audit;
+#[cfg(feature = "busylight")]
+mod busylight;
+mod cameras;
+mod config;
+#[cfg(unix)]
+mod control;
+#[cfg(feature = "dbus")]
+mod dbus;
+mod debounce;
+#[cfg(target_os = "linux")]
+mod gpio;
+#[cfg(feature = "grpc")]
+mod grpc;
+#[cfg(feature = "home-assistant")]
+mod homeassistant;
+mod hooks;
+mod host;
+#[cfg(feature = "http")]
+mod http;
+pub mod monitor;
+mod mqtt;
+#[cfg(feature = "otel")]
+mod otel;
+mod output;
+mod peers;
+mod persist;
+#[cfg(target_os = "linux")]
+mod privileges;
+pub mod process;
+mod resume;
+mod schedule;
+mod security;
+#[cfg(feature = "sessions")]
+mod sessions;
+mod signals;
+mod stats;
+mod status;
+mod template;
+#[cfg(feature = "testing")]
+pub mod testing;
+mod v4l;
+
+use monitor::DeviceEventKind;
+use output::OutputMode;
+
+#[derive(
+    clap::ValueEnum, serde::Serialize, serde::Deserialize, Debug, Default, PartialEq, Eq, Clone,
+)]
+#[serde(rename_all = "lowercase")]
+enum CameraState {
+    On,
+    #[default]
+    Off,
+}
+
+impl CameraState {
+    /// the payload homeassistant expects for the binary sensor
+    fn as_payload(&self) -> &'static str {
+        match self {
+            CameraState::On => "ON",
+            CameraState::Off => "OFF",
+        }
+    }
+}
+
+#[derive(Parser, Debug)]
+struct Args {
+    #[clap(subcommand)]
+    command: Command,
+
+    /// TOML file with per-device settings
+    #[clap(long, global = true)]
+    config: Option<PathBuf>,
+
+    /// SQLite database to record camera sessions into
+    #[cfg(feature = "sessions")]
+    #[clap(long, global = true)]
+    session_db: Option<PathBuf>,
+
+    /// unix socket to take commands like `simulate` on
+    #[cfg(unix)]
+    #[clap(long, global = true)]
+    control_socket: Option<PathBuf>,
+
+    /// host of the MQTT server you are connecting to
+    #[clap(long, global = true, default_value = "localhost")]
+    mqtt_host: String,
+    /// port of the MQTT server you are connecting to
+    #[clap(long, global = true, default_value = "1883")]
+    mqtt_port: u16,
+    /// unix socket of the MQTT server, in place of `--mqtt-host` and `--mqtt-port`
+    #[cfg(unix)]
+    #[clap(long, global = true, conflicts_with_all = ["mqtt_host", "mqtt_port"])]
+    mqtt_socket: Option<PathBuf>,
+}
+
+impl Args {
+    /// the main broker, from `--mqtt-socket` or `--mqtt-host` and `--mqtt-port`
+    fn broker(&self) -> mqtt::BrokerAddress {
+        #[cfg(unix)]
+        if let Some(path) = &self.mqtt_socket {
+            return mqtt::BrokerAddress::Unix(path.clone());
+        }
+
+        mqtt::BrokerAddress::Tcp(self.mqtt_host.clone(), self.mqtt_port)
+    }
+}
+
+/// options for `run`
+#[derive(clap::Args, Debug)]
+struct RunArgs {
+    /// fallback broker as `host` or `host:port` for when the main one is down, can be repeated
+    #[clap(long)]
+    mqtt_fallback: Vec<String>,
+    /// how long in seconds a broker has to be unreachable before failing over to the next one
+    #[clap(long, default_value = "30")]
+    mqtt_failover_after: u64,
+    /// how often in seconds to check whether the main broker is back while on a fallback
+    #[clap(long, default_value = "300")]
+    mqtt_failback_interval: u64,
+    /// client id to connect with, two clients with the same id keep kicking each other off the
+    /// broker. defaults to `camera-snitch-<hostname>`
+    #[clap(long)]
+    mqtt_client_id: Option<String>,
+    /// add a random suffix to the client id, for when several instances could end up with the same
+    /// one, e.g. cloned machines or containers sharing a hostname
+    #[clap(long)]
+    mqtt_client_id_suffix: bool,
+    /// keepalive in seconds
+    #[clap(long, default_value = "60")]
+    mqtt_keepalive: u64,
+    #[clap(long, default_value = "1000")]
+    mqtt_pending_throttle: u64,
+    /// upper bound in seconds for the exponential backoff between reconnect attempts
+    #[clap(long, default_value = "60")]
+    mqtt_reconnect_max: u64,
+    /// how many topics' worth of publishes to hold on to while the broker is unreachable
+    #[clap(long, default_value = "64")]
+    mqtt_offline_queue: usize,
+
+    /// how HA tells the entities are gone, `expiry` is for brokers that purge retained messages
+    #[clap(long, value_enum, default_value_t)]
+    availability: mqtt::AvailabilityMode,
+    /// set `expire_after` on the entities that get refreshed, so HA marks them unavailable after
+    /// this many seconds without an update
+    #[clap(long, required_if_eq("availability", "expiry"))]
+    expire_after: Option<u64>,
+    /// republish the current state every this many seconds, defaults to half of `--expire-after`
+    #[clap(long)]
+    refresh_interval: Option<u64>,
+
+    /// base url of the home assistant instance for `--output home-assistant`
+    #[cfg(feature = "home-assistant")]
+    #[clap(long, default_value = "http://homeassistant.local:8123")]
+    ha_url: String,
+    /// long-lived access token for `--output home-assistant`
+    #[cfg(feature = "home-assistant")]
+    #[clap(long)]
+    ha_token: Option<String>,
+
+    /// how to watch the devices, `poll` scans /proc for environments that restrict inotify on /dev
+    #[clap(long, value_enum, default_value_t)]
+    backend: monitor::Backend,
+    /// how often the polling backends check the devices, in milliseconds
+    #[clap(long, default_value = "1000")]
+    poll_interval: u64,
+    /// play back the device events in this JSONL file instead of watching the cameras, the daemon
+    /// stops once it's through
+    #[cfg(feature = "testing")]
+    #[clap(long)]
+    script: Option<PathBuf>,
+
+    /// only count a camera as on once whoever opened it is streaming from it, rather than just
+    /// opening it to look at what it can do. checked every `--poll-interval`, an open that lasts
+    /// five checks counts either way
+    #[cfg(target_os = "linux")]
+    #[clap(long)]
+    require_streaming: bool,
+    /// once the camera has been on this many seconds, check /proc that something really still has
+    /// it open and turn it off if not, then keep checking that often. a safety net for close
+    /// events that got lost, e.g. over a suspend
+    #[cfg(target_os = "linux")]
+    #[clap(long)]
+    max_on: Option<u64>,
+
+    /// debounce duration in milliseconds, tune this to what works on your system
+    #[clap(long, default_value = "300")]
+    debounce_duration: u64,
+    /// least time in milliseconds between two published state changes, anything in between is
+    /// coalesced into the latest state. unlike the debounce this also holds back changes that
+    /// are real
+    #[clap(long, default_value = "0")]
+    min_publish_interval: u64,
+    /// how long in milliseconds the camera has to stay on before it's reported on
+    #[clap(long, default_value = "0")]
+    on_delay: u64,
+    /// how long in milliseconds the camera has to stay off before it's reported off
+    #[clap(long, default_value = "0")]
+    off_delay: u64,
+
+    /// file to keep the daily/weekly usage counters in, so they survive restarts. defaults to
+    /// `<name>.stats.json` next to the `--state-file`, if there is one
+    #[clap(long)]
+    stats_file: Option<PathBuf>,
+    /// file to keep the published state and the running session in, so restarting mid-call
+    /// carries on with the same session. the usage counters go next to it unless `--stats-file`
+    /// says otherwise
+    #[clap(long)]
+    state_file: Option<PathBuf>,
+
+    /// how often to update the session duration and usage sensors while the camera is on, in seconds
+    #[clap(long, default_value = "30")]
+    session_duration_interval: u64,
+
+    /// where to report state changes, the status bar modes print JSON to stdout and skip MQTT entirely
+    #[clap(long, value_enum, default_value = "mqtt")]
+    output: OutputMode,
+
+    /// send a desktop notification over D-Bus whenever the camera turns on or off
+    #[cfg(feature = "dbus")]
+    #[clap(long)]
+    desktop_notifications: bool,
+
+    /// register a `dev.wseaton.CameraSnitch` D-Bus service exposing the current state
+    #[cfg(feature = "dbus")]
+    #[clap(long)]
+    dbus_service: bool,
+    /// which bus to register the D-Bus service on, the system bus needs a policy allowing the name
+    #[cfg(feature = "dbus")]
+    #[clap(long, value_enum, default_value = "session")]
+    dbus_bus: dbus::Bus,
+
+    /// shell command to run when the camera turns on, see the readme for the environment it gets
+    #[clap(long)]
+    on_camera_on: Option<String>,
+    /// shell command to run when the camera turns off
+    #[clap(long)]
+    on_camera_off: Option<String>,
+
+    /// append a JSON line for every device event to this file, for auditing independent of the broker
+    #[clap(long)]
+    audit_log: Option<PathBuf>,
+    /// rotate the audit log once it grows past this many bytes
+    #[clap(long, default_value = "10485760")]
+    audit_log_max_size: u64,
+    /// how many rotated audit logs to keep
+    #[clap(long, default_value = "5")]
+    audit_log_keep: u32,
+
+    /// switch to this user once the devices are being watched, keeping only what process
+    /// attribution needs
+    #[cfg(target_os = "linux")]
+    #[clap(long)]
+    user: Option<String>,
+    /// group to switch to with `--user`, defaults to the user's primary group
+    #[cfg(target_os = "linux")]
+    #[clap(long, requires = "user")]
+    group: Option<String>,
+
+    /// serve the current state on `/state` and health on `/healthz` from this address, like
+    /// `127.0.0.1:8080`
+    #[cfg(feature = "http")]
+    #[clap(long)]
+    http_listen: Option<std::net::SocketAddr>,
+    /// serve the gRPC API in proto/camera_snitch.proto from this address, like `127.0.0.1:50051`
+    #[cfg(feature = "grpc")]
+    #[clap(long)]
+    grpc_listen: Option<std::net::SocketAddr>,
+
+    /// export traces and counters over OTLP/HTTP to the collector at this url, like
+    /// `http://localhost:4318`
+    #[cfg(feature = "otel")]
+    #[clap(long)]
+    otel_endpoint: Option<String>,
+
+    #[cfg(feature = "busylight")]
+    #[clap(flatten)]
+    busylight: busylight::BusylightArgs,
+
+    #[cfg(target_os = "linux")]
+    #[clap(flatten)]
+    gpio: gpio::GpioArgs,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// watch the cameras and report on them, this is the daemon
+    Run(Box<RunArgs>),
+    /// print the current state, from the daemon's `--control-socket` if there is one or else from
+    /// what's retained on the broker
+    Status,
+    /// publish the Home Assistant discovery configs and exit
+    Discover,
+    /// clear everything we've retained on the broker, which removes the entities from Home
+    /// Assistant
+    Cleanup,
+    /// print recent camera sessions from the `--session-db` database
+    #[cfg(feature = "sessions")]
+    Sessions {
+        /// how many sessions to show
+        #[clap(long, default_value = "20")]
+        limit: u32,
+    },
+    /// pretend a camera turned on or off, through the daemon's `--control-socket` if there is
+    /// one or else by publishing the state change straight to the broker
+    Simulate {
+        #[clap(long, default_value = "/dev/video0")]
+        device: PathBuf,
+        #[clap(long, value_enum)]
+        state: CameraState,
+    },
+}
+
+/// options for the one-off commands that talk to the broker themselves
+///
+/// these get their own client id, taking over the daemon's would knock it offline, and no last
+/// will for the same reason
+fn oneshot_options(args: &Args, command: &str) -> MqttOptions {
+    args.broker().options(format!("camera-snitch-{}", command))
+}
+
+/// connects a one-off command, publishing on the topics from the config like the daemon
+async fn connect_oneshot(
+    args: &Args,
+    command: &str,
+) -> anyhow::Result<(mqtt::Publisher, rumqttc::EventLoop)> {
+    let topics = mqtt::Topics::new(&load_config(args)?.topics)?;
+    let (mut client, eventloop) = mqtt::connect_once(oneshot_options(args, command)).await?;
+    client.set_topics(topics);
+
+    Ok((client, eventloop))
+}
+
+fn load_config(args: &Args) -> anyhow::Result<config::Config> {
+    match &args.config {
+        Some(path) => config::Config::load(path),
+        None => Ok(config::Config::default()),
+    }
+}
+
+/// the `status` subcommand
+async fn status(args: &Args) -> anyhow::Result<()> {
+    #[cfg(unix)]
+    if let Some(path) = &args.control_socket {
+        let response = control::send(path, &control::Request::Status).await?;
+        if let Some(status) = response.status {
+            println!("state: {}", status.state.as_payload());
+            if let Some(device) = &status.device {
+                println!("device: {}", device.display());
+            }
+            if let Some(used_by) = &status.used_by {
+                println!("used by: {}", used_by);
+            }
+            if let Some(since) = &status.since {
+                println!("since: {}", since);
+            }
+        }
+        return Ok(());
+    }
+
+    let (mut client, mut eventloop) = connect_oneshot(args, "status").await?;
+    let values = mqtt::read_status(&mut client, &mut eventloop).await?;
+    mqtt::disconnect(&mut client, &mut Some(eventloop)).await;
+
+    if values.is_empty() {
+        anyhow::bail!("nothing retained on the broker, has the daemon ever run?");
+    }
+    for (name, value) in values {
+        println!("{}: {}", name, value);
+    }
+
+    Ok(())
+}
+
+/// the `discover` subcommand
+async fn discover(args: &Args) -> anyhow::Result<()> {
+    let devices = monitor::find_devices()?;
+    let cameras = cameras::Cameras::new(&devices).present(&devices);
+
+    let (mut client, eventloop) = connect_oneshot(args, "discover").await?;
+    mqtt::write_discovery(&mut client, &cameras)?;
+    mqtt::disconnect(&mut client, &mut Some(eventloop)).await;
+
+    Ok(())
+}
+
+/// the `cleanup` subcommand
+async fn cleanup(args: &Args) -> anyhow::Result<()> {
+    let (mut client, mut eventloop) = connect_oneshot(args, "cleanup").await?;
+    mqtt::cleanup(&mut client, &mut eventloop).await?;
+    mqtt::disconnect(&mut client, &mut Some(eventloop)).await;
+
+    Ok(())
+}
+
+/// the `simulate` subcommand
+async fn simulate(
+    args: &Args,
+    device: &std::path::Path,
+    state: &CameraState,
+) -> anyhow::Result<()> {
+    #[cfg(unix)]
+    if let Some(path) = &args.control_socket {
+        let kind = match state {
+            CameraState::On => DeviceEventKind::Opened,
+            CameraState::Off => DeviceEventKind::Closed,
+        };
+        let request = control::Request::Simulate {
+            device: device.to_path_buf(),
+            kind,
+        };
+        return control::send(path, &request).await.map(|_| ());
+    }
+
+    let (mut client, eventloop) = connect_oneshot(args, "simulate").await?;
+    mqtt::send_event(&mut client, state);
+    mqtt::send_last_used(&mut client, device, chrono::Utc::now());
+    mqtt::disconnect(&mut client, &mut Some(eventloop)).await;
+
+    Ok(())
+}
+
+/// lets every option come from a `CAMERA_SNITCH_<OPTION>` environment variable too, like
+/// `CAMERA_SNITCH_MQTT_HOST` for `--mqtt-host`, for containers where flags are awkward. a flag on
+/// the command line wins over the variable
+///
+/// the values stay out of `--help`, some of them are secrets
+fn with_env(command: clap::Command) -> clap::Command {
+    let subcommands: Vec<String> = command
+        .get_subcommands()
+        .map(|subcommand| subcommand.get_name().to_string())
+        .collect();
+
+    let command = command.mut_args(|arg| {
+        let env = format!("CAMERA_SNITCH_{}", arg.get_id().as_str().to_uppercase());
+        arg.env(env).hide_env_values(true)
+    });
+    subcommands.iter().fold(command, |command, name| {
+        command.mut_subcommand(name, with_env)
+    })
+}
+
+/// parses the command line and runs the subcommand, this is all `main` does
+///
+/// meant for a single threaded runtime, see `main.rs`
+pub async fn cli() -> anyhow::Result<()> {
+    let args = with_env(Args::command()).get_matches();
+    let args = Args::from_arg_matches(&args).unwrap_or_else(|e| e.exit());
+
+    #[cfg(feature = "otel")]
+    let (otel, otel_exporter) = match &args.command {
+        Command::Run(run) => run
+            .otel_endpoint
+            .as_deref()
+            .map(otel::layer)
+            .transpose()?
+            .unzip(),
+        _ => (None, None),
+    };
+    // logs go to stderr so stdout stays clean for the status bar output modes
+    let registry = tracing_subscriber::registry().with(
+        tracing_subscriber::fmt::layer()
+            .with_writer(std::io::stderr)
+            .with_filter(LevelFilter::INFO),
+    );
+    #[cfg(feature = "otel")]
+    let registry = registry.with(otel.with_filter(LevelFilter::INFO));
+    registry.init();
+
+    let res = match &args.command {
+        Command::Run(run) => daemon(&args, run).await,
+        Command::Status => status(&args).await,
+        Command::Discover => discover(&args).await,
+        Command::Cleanup => cleanup(&args).await,
+        #[cfg(feature = "sessions")]
+        Command::Sessions { limit } => {
+            let Some(path) = &args.session_db else {
+                anyhow::bail!("--session-db is required to list sessions");
+            };
+            sessions::print_recent(path, *limit)
+        }
+        Command::Simulate { device, state } => simulate(&args, device, state).await,
+    };
+    #[cfg(feature = "otel")]
+    if let Some(exporter) = otel_exporter {
+        exporter.shutdown();
+    }
+
+    res
+}
+
+/// the `run` subcommand
+async fn daemon(args: &Args, run: &RunArgs) -> anyhow::Result<()> {
+    #[cfg(feature = "testing")]
+    let script = run
+        .script
+        .as_deref()
+        .map(testing::ScriptedMonitor::load)
+        .transpose()?
+        .map(|script| {
+            let devices = script.devices();
+            (devices, Box::new(script) as Box<dyn monitor::DeviceMonitor>)
+        });
+    #[cfg(not(feature = "testing"))]
+    let script = None;
+
+    #[cfg(feature = "testing")]
+    let capture = (run.output == OutputMode::Capture).then(testing::Capture::default);
+    #[cfg(feature = "testing")]
+    let publisher = capture.clone().map(mqtt::Publisher::capture);
+    #[cfg(not(feature = "testing"))]
+    let publisher = None;
+
+    pipeline(args, run, script, publisher).await?;
+
+    #[cfg(feature = "testing")]
+    if let Some(capture) = capture {
+        capture.print()?;
+    }
+
+    Ok(())
+}
+
+/// everything `run` does once it's up, from the device events to the outputs
+///
+/// `fake` stands in for the cameras, the devices there at startup and the monitor playing events
+/// for them, and nothing gets looked up in `/proc` then. `publisher` is what gets published to
+/// in place of whatever the output mode has, for `--output capture`
+async fn pipeline(
+    args: &Args,
+    run: &RunArgs,
+    fake: Option<(Vec<PathBuf>, Box<dyn monitor::DeviceMonitor>)>,
+    publisher: Option<mqtt::Publisher>,
+) -> anyhow::Result<()> {
+    let config = load_config(args)?;
+    let topics = mqtt::Topics::new(&config.topics)?;
+
+    let scripted = fake.is_some();
+    let resync = resume::watch();
+    let mut resynced = resync.subscribe();
+    let (mut devices, device_monitor) = match fake {
+        Some(fake) => fake,
+        None => {
+            let devices = monitor::find_devices()?;
+            let device_monitor =
+                run.backend
+                    .monitor(&devices, Duration::from_millis(run.poll_interval), resync);
+            (devices, device_monitor)
+        }
+    };
+    let mut cameras = cameras::Cameras::new(&devices);
+    #[cfg(target_os = "linux")]
+    let device_monitor: Box<dyn monitor::DeviceMonitor> = match run.require_streaming {
+        true => Box::new(monitor::StreamingFilter::new(
+            device_monitor,
+            Duration::from_millis(run.poll_interval),
+        )),
+        false => device_monitor,
+    };
+    let (events_tx, mut events) = monitor::start(device_monitor)?;
+    let (status, status_rx) = tokio::sync::watch::channel(status::Status {
+        devices: devices
+            .iter()
+            .map(|device| (device.clone(), CameraState::Off))
+            .collect(),
+        mqtt_connected: run.output.uses_mqtt().then_some(false),
+        ..Default::default()
+    });
+    // every open, close and state change as it happens, the status only has the latest
+    let api_events = status::events();
+    #[cfg(unix)]
+    if let Some(path) = &args.control_socket {
+        control::listen(path, events_tx.downgrade(), status_rx.clone())?;
+    }
+    // inotify and eBPF only see what happens from here on, so whatever already has a camera open
+    // goes in as an open. a script's devices have nothing to do with what's in /proc, its events
+    // say who has them open
+    let openers = match scripted {
+        true => Vec::new(),
+        false => process::find_openers(&devices),
+    };
+    let already_open: HashSet<PathBuf> = openers
+        .iter()
+        .map(|process| process.device.clone())
+        .collect();
+    // carry on with the session from before a restart, as long as the camera is still open.
+    // those devices go in as opens like any other, they just don't change anything
+    let state_file = run.state_file.as_deref().map(persist::StateFile::new);
+    let mut saved = state_file
+        .as_ref()
+        .and_then(persist::StateFile::load)
+        .unwrap_or_default();
+    saved.open.retain(|device| already_open.contains(device));
+    if saved.open.is_empty() {
+        saved.end_session();
+    } else {
+        tracing::info!(
+            "picking up the session from before the restart: {:?}",
+            saved.open
+        );
+        let openers: Vec<_> = openers
+            .into_iter()
+            .filter(|process| saved.open.contains(&process.device))
+            .collect();
+        status.send_modify(|status| {
+            status.state = saved.state.clone();
+            status.used_by = process::describe(&openers);
+            status.since = saved.session_start.map(|start| {
+                start
+                    .with_timezone(&chrono::Local)
+                    .to_rfc3339_opts(chrono::SecondsFormat::Secs, false)
+            });
+            for device in &saved.open {
+                status.devices.insert(device.clone(), CameraState::On);
+            }
+        });
+    }
+    for device in already_open {
+        tracing::info!("{:?} is already open", device);
+        let event = monitor::DeviceEvent {
+            device,
+            kind: DeviceEventKind::Opened,
+            process: None,
+        };
+        if let Err(e) = events_tx.try_send(event) {
+            tracing::error!("error passing on an already open camera: {}", e);
+        }
+    }
+    #[cfg(target_os = "linux")]
+    let max_on = run.max_on.map(Duration::from_secs).filter(|_| !scripted);
+    // /proc is the only way to check that's there
+    #[cfg(not(target_os = "linux"))]
+    let max_on: Option<Duration> = None;
+    // the failsafe and resumes correct the state by feeding in the events that got missed, weak
+    // so the monitor going away still ends the loop
+    let own_tx = events_tx.downgrade();
+    drop(events_tx);
+    #[cfg(feature = "http")]
+    if let Some(addr) = run.http_listen {
+        http::serve(addr, status_rx.clone(), api_events.subscribe()).await?;
+    }
+    #[cfg(feature = "grpc")]
+    if let Some(addr) = run.grpc_listen {
+        grpc::serve(addr, status_rx.clone(), api_events.subscribe()).await?;
+    }
+    drop(status_rx);
+
+    // the primary and then the fallbacks, the peers connection fails over between them too
+    let mut addresses = Vec::new();
+    if run.output.uses_mqtt() {
+        addresses.push(args.broker());
+        for fallback in &run.mqtt_fallback {
+            addresses.push(mqtt::parse_broker(fallback, args.mqtt_port)?);
+        }
+    }
+
+    let (mut brokers, mut client, mut eventloop) = if run.output.uses_mqtt() {
+        let mut client_id = run
+            .mqtt_client_id
+            .clone()
+            .unwrap_or_else(|| format!("camera-snitch-{}", host::hostname()));
+        if run.mqtt_client_id_suffix {
+            client_id = format!("{}-{}", client_id, host::random_id(4));
+        }
+        tracing::info!("mqtt client id is {}", client_id);
+
+        let options = addresses
+            .iter()
+            .cloned()
+            .map(|address| {
+                let mut mqttoptions = address.options(&client_id);
+                mqttoptions.set_keep_alive(Duration::from_secs(run.mqtt_keepalive));
+                mqttoptions.set_pending_throttle(Duration::from_micros(run.mqtt_pending_throttle));
+                mqtt::set_last_will(&mut mqttoptions, &topics);
+                (address, mqttoptions)
+            })
+            .collect();
+        let brokers = mqtt::Brokers::new(options, Duration::from_secs(run.mqtt_failover_after));
+        let (client, eventloop) = brokers.connect();
+
+        (
+            Some(brokers),
+            Some(mqtt::Publisher::new(client, run.mqtt_offline_queue)),
+            Some(eventloop),
+        )
+    } else if run.output == OutputMode::Stdout {
+        (None, Some(mqtt::Publisher::stdout()), None)
+    } else {
+        (None, None, None)
+    };
+    if let Some(publisher) = publisher {
+        client = Some(publisher);
+    }
+    if let Some(client) = client.as_mut() {
+        client.set_topics(topics);
+        client.set_discovery_options(mqtt::DiscoveryOptions {
+            availability: run.availability,
+            expire_after: run.expire_after,
+        });
+    }
+
+    let peers = match run.output.uses_mqtt() {
+        true => peers::Peers::from_config(
+            &config.peers,
+            &addresses,
+            Duration::from_secs(run.mqtt_keepalive),
+            Duration::from_secs(run.mqtt_failover_after),
+            Duration::from_secs(run.mqtt_failback_interval),
+        )?,
+        false => None,
+    };
+
+    #[cfg(feature = "home-assistant")]
+    let home_assistant = if run.output == OutputMode::HomeAssistant {
+        let Some(token) = run.ha_token.clone() else {
+            anyhow::bail!("--ha-token is required for --output home-assistant");
+        };
+        Some(homeassistant::RestClient::start(&run.ha_url, token)?)
+    } else {
+        None
+    };
+
+    #[cfg(feature = "alerts")]
+    let mut alerts = alerts::Alerts::from_config(&config)?;
+    #[cfg(not(feature = "alerts"))]
+    if !config.alerts.is_empty() {
+        anyhow::bail!("`[[alerts]]` in the config needs a build with the `alerts` feature");
+    }
+
+    let mut tripwire = security::Tripwire::from_config(&config)?;
+
+    // security alerts go to the desktop too, even without state notifications
+    #[cfg(feature = "dbus")]
+    let mut notifier = if run.desktop_notifications || tripwire.is_some() {
+        match dbus::Notifier::connect().await {
+            Ok(notifier) => Some(notifier),
+            Err(e) => {
+                tracing::error!("desktop notifications disabled, no session bus: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    #[cfg(feature = "dbus")]
+    let service = if run.dbus_service {
+        Some(dbus::Service::start(run.dbus_bus).await?)
+    } else {
+        None
+    };
+
+    #[cfg(feature = "busylight")]
+    let busylight = busylight::Busylight::from_args(&run.busylight)?;
+    #[cfg(target_os = "linux")]
+    let gpio = gpio::Gpio::from_args(&run.gpio)?;
+
+    #[cfg(feature = "sessions")]
+    let mut session_log = match &args.session_db {
+        Some(path) => Some(sessions::SessionLog::open(path)?),
+        None => None,
+    };
+    // the shutdown closed the session's row, it isn't over after all
+    #[cfg(feature = "sessions")]
+    if let (Some(session_log), CameraState::On) = (session_log.as_mut(), &saved.state) {
+        session_log.reopen();
+    }
+
+    let mut audit_log = match &run.audit_log {
+        Some(path) => Some(audit::AuditLog::open(
+            path,
+            run.audit_log_max_size,
+            run.audit_log_keep,
+        )?),
+        None => None,
+    };
+
+    // everything that needs root is set up by now
+    #[cfg(target_os = "linux")]
+    if let Some(user) = &run.user {
+        privileges::drop_to(user, run.group.as_deref())?;
+    }
+
+    let mut last_state = saved.state.clone();
+    // devices whose last event was an open, the published state is whether there are any
+    let mut open = saved.open.clone();
+    // the process behind the last open of each device, for backends that say who it was
+    let mut reported: HashMap<PathBuf, process::ProcessInfo> = HashMap::new();
+    // what quiet hours kept off the broker, published once they're over
+    let mut quiet_attributes: Option<Vec<process::ProcessInfo>> = None;
+    let mut quiet_last_used: HashMap<PathBuf, chrono::DateTime<chrono::Utc>> = HashMap::new();
+
+    // give the bar something to show before the first event comes in
+    output::print_state(run.output, &last_state)?;
+
+    let mut debouncer = debounce::Debouncer::new(
+        Duration::from_millis(run.debounce_duration),
+        config.debounce_windows(),
+        Duration::from_millis(run.on_delay),
+        Duration::from_millis(run.off_delay),
+        Duration::from_millis(run.min_publish_interval),
+    );
+
+    let mut session_start = saved.session_instant();
+    // what the session duration sensor shows while the camera is off
+    let mut last_session = Duration::from_secs(saved.last_session);
+    if let Some(state_file) = &state_file {
+        state_file.save(&last_state, &open, session_start, last_session);
+    }
+    // when camera time was last added to the usage stats, while a session is running
+    let mut last_accrued = tokio::time::Instant::now();
+    let stats_file = run
+        .stats_file
+        .clone()
+        .or_else(|| run.state_file.as_deref().map(persist::stats_file));
+    let mut stats = stats::UsageStats::load(stats_file.as_deref());
+    // nothing is going to connect, so print what a fresh connection would publish right away
+    if let (None, Some(client)) = (&brokers, client.as_mut()) {
+        mqtt::on_connect(
+            client,
+            &cameras,
+            &devices,
+            &last_state,
+            &open,
+            &stats,
+            false,
+        )?;
+        mqtt::send_application(client, None);
+    }
+    // the first connection checks the retained state before overwriting it, until this deadline
+    let mut reconcile_until: Option<tokio::time::Instant> = None;
+    let mut reconciled = false;
+    let mut backoff = mqtt::Backoff::new(Duration::from_secs(run.mqtt_reconnect_max));
+    let mut signals = signals::Signals::new()?;
+    let failback_interval = Duration::from_secs(run.mqtt_failback_interval);
+    let mut failback_ticker = tokio::time::interval_at(
+        tokio::time::Instant::now() + failback_interval,
+        failback_interval,
+    );
+    failback_ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let mut session_ticker =
+        tokio::time::interval(Duration::from_secs(run.session_duration_interval));
+    let refresh_interval = run
+        .refresh_interval
+        .or(run.expire_after.map(|expire_after| expire_after / 2))
+        .map(|secs| Duration::from_secs(secs.max(1)));
+    // the period doesn't matter without a refresh interval, the ticker is never polled then
+    let refresh_period = refresh_interval.unwrap_or(failback_interval);
+    let mut refresh_ticker =
+        tokio::time::interval_at(tokio::time::Instant::now() + refresh_period, refresh_period);
+    refresh_ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    // a picked up session never goes through a change, so tell the outputs that start out off.
+    // hooks, alerts and notifications already went off for it before the restart
+    if last_state == CameraState::On {
+        #[cfg_attr(
+            not(any(feature = "dbus", feature = "home-assistant")),
+            allow(unused_variables)
+        )]
+        let used_by = status.borrow().used_by.clone();
+        #[cfg(feature = "dbus")]
+        if let Some(service) = service.as_ref() {
+            service.set_state(&last_state, used_by.as_deref()).await;
+        }
+        if !config
+            .quiet_hours
+            .is_quiet(chrono::Local::now().naive_local())
+        {
+            #[cfg(feature = "home-assistant")]
+            if let Some(home_assistant) = home_assistant.as_ref() {
+                home_assistant.set_state(&last_state, used_by.as_deref());
+            }
+            if let Some(peers) = peers.as_ref() {
+                peers.set_state(&last_state);
+            }
+            #[cfg(feature = "busylight")]
+            if let Some(busylight) = busylight.as_ref() {
+                busylight.set_state(&last_state);
+            }
+            #[cfg(target_os = "linux")]
+            if let Some(gpio) = gpio.as_ref() {
+                gpio.set_state(&last_state);
+            }
+        }
+    }
+    // when the failsafe next checks that the camera is really still on
+    let mut verify_at = max_on
+        .filter(|_| last_state == CameraState::On)
+        .map(|max_on| tokio::time::Instant::now() + max_on);
+
+    loop {
+        let deadline = debouncer.deadline();
+
+        let change = tokio::select! {
+            event = events.recv() => {
+                let Some(mut event) = event else {
+                    if scripted {
+                        break;
+                    }
+                    anyhow::bail!("device monitor stopped");
+                };
+                #[cfg(feature = "otel")]
+                otel::DEVICE_EVENTS.inc();
+                let span = tracing::info_span!(
+                    "device_event",
+                    device = ?event.device,
+                    kind = event.kind.as_str()
+                );
+                let entered = span.enter();
+
+                if let DeviceEventKind::Added | DeviceEventKind::Removed = event.kind {
+                    let present = event.kind == DeviceEventKind::Added;
+                    tracing::info!("camera {}: {:?}", event.kind.as_str(), event.device);
+                    if present && !devices.contains(&event.device) {
+                        devices.push(event.device.clone());
+                        cameras.add(&event.device, &devices);
+                    } else if !present {
+                        devices.retain(|device| *device != event.device);
+                    }
+                    // a camera with other nodes still there stays available
+                    let camera = cameras.camera(&event.device).to_path_buf();
+                    let camera_present = cameras.present(&devices).contains(&camera);
+
+                    if let Some(audit_log) = audit_log.as_mut() {
+                        audit_log.write(&audit::AuditEntry {
+                            timestamp: chrono::Utc::now()
+                                .to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+                            device: &event.device,
+                            transition: event.kind.as_str(),
+                            processes: &[],
+                            debounce_suppressed: false,
+                        });
+                    }
+                    status.send_modify(|status| match present {
+                        true => {
+                            status.devices.insert(event.device.clone(), CameraState::Off);
+                        }
+                        false => {
+                            status.devices.remove(&event.device);
+                        }
+                    });
+                    if let Some(client) = client.as_mut() {
+                        if present {
+                            mqtt::write_discovery(client, &cameras.present(&devices))?;
+                            mqtt::send_device_state(client, &camera, &cameras.state(&camera, &open));
+                        }
+                        mqtt::send_device_availability(client, &camera, camera_present);
+                    }
+
+                    // whatever had it open won't be closing it through a watch that's gone, so
+                    // the removal counts as the close
+                    if present || !open.contains(&event.device) {
+                        continue;
+                    }
+                    event.kind = DeviceEventKind::Closed;
+                }
+
+                let current_state = match event.kind {
+                    DeviceEventKind::Opened => {
+                        tracing::info!("camera opened");
+                        CameraState::On
+                    }
+                    _ => {
+                        tracing::info!("camera closed");
+                        CameraState::Off
+                    }
+                };
+                let current_device = event.device;
+                let transition = event.kind.as_str();
+
+                let device_changed = match current_state {
+                    CameraState::On => open.insert(current_device.clone()),
+                    CameraState::Off => open.remove(&current_device),
+                };
+                match (&current_state, &event.process) {
+                    (CameraState::On, Some(process)) => {
+                        reported.insert(current_device.clone(), process.clone());
+                    }
+                    (CameraState::Off, _) => {
+                        reported.remove(&current_device);
+                    }
+                    _ => {}
+                }
+                // we only send an event if the state has changed over the debounce window
+                //
+                // This is required because the camera will open and close multiple times when it is first plugged in or
+                // opened by a browser and we don't want to send multiple events for that.
+                //
+                // each device is debounced with its own window, the combined state follows from them
+                let outcome = debouncer.observe(current_state.clone(), &current_device, &last_state);
+
+                // backends like eBPF tell us exactly who it was, otherwise go looking in /proc
+                let processes = match (event.process, &current_state) {
+                    (Some(process), _) => vec![process],
+                    (None, CameraState::On)
+                        if !scripted
+                            && (audit_log.is_some() || client.is_some() || tripwire.is_some()) =>
+                    {
+                        process::find_openers(std::slice::from_ref(&current_device))
+                    }
+                    _ => Vec::new(),
+                };
+
+                if let Some(audit_log) = audit_log.as_mut() {
+                    audit_log.write(&audit::AuditEntry {
+                        timestamp: chrono::Utc::now()
+                            .to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+                        device: &current_device,
+                        transition,
+                        processes: &processes,
+                        debounce_suppressed: outcome == debounce::Outcome::Deferred,
+                    });
+                }
+                if device_changed {
+                    status.send_modify(|status| {
+                        status.devices.insert(current_device.clone(), current_state.clone());
+                    });
+                    let kind = match current_state {
+                        CameraState::On => status::EventKind::DeviceOpened,
+                        CameraState::Off => status::EventKind::DeviceClosed,
+                    };
+                    let _ = api_events.send(status::Event::new(
+                        kind,
+                        current_device.clone(),
+                        current_state.clone(),
+                        process::describe(&processes),
+                    ));
+                    if let Some(state_file) = &state_file {
+                        state_file.save(&last_state, &open, session_start, last_session);
+                    }
+                }
+                let quiet = config.quiet_hours.is_quiet(chrono::Local::now().naive_local());
+                if let Some(client) = client.as_mut().filter(|_| !quiet) {
+                    if device_changed {
+                        let camera = cameras.camera(&current_device);
+                        mqtt::send_device_state(client, camera, &cameras.state(camera, &open));
+                    }
+                    mqtt::send_device_event(
+                        client,
+                        transition,
+                        &current_device,
+                        process::describe(&processes).as_deref(),
+                    );
+                }
+
+                // not across the awaits below, whatever else runs meanwhile isn't part of it
+                drop(entered);
+
+                if let (Some(tripwire), DeviceEventKind::Opened) = (tripwire.as_mut(), event.kind) {
+                    for process in tripwire.check(&processes) {
+                        tracing::warn!("unexpected camera access by {:?}", process);
+                        if let Some(client) = client.as_mut() {
+                            mqtt::send_security_alert(client, process);
+                        }
+                        #[cfg(feature = "dbus")]
+                        if let Some(notifier) = notifier.as_mut() {
+                            notifier.notify_security(process).await;
+                        }
+                        #[cfg(feature = "alerts")]
+                        tripwire.send_webhook(process);
+                    }
+                }
+
+                match outcome {
+                    debounce::Outcome::Ready => debouncer.take(),
+                    _ => None,
+                }
+            }
+            _ = tokio::time::sleep_until(deadline.unwrap_or_else(tokio::time::Instant::now)), if deadline.is_some() => {
+                debouncer.take()
+            }
+            _ = session_ticker.tick(), if session_start.is_some() => {
+                stats.add_usage(last_accrued.elapsed());
+                last_accrued = tokio::time::Instant::now();
+
+                if let (Some(client), Some(start)) = (client.as_mut(), session_start) {
+                    mqtt::send_session_duration(client, start.elapsed());
+                    mqtt::send_stats(client, &stats);
+                }
+                // keeps the state file recent enough to get picked up after a restart
+                if let Some(state_file) = &state_file {
+                    state_file.save(&last_state, &open, session_start, last_session);
+                }
+                None
+            }
+            _ = tokio::time::sleep_until(verify_at.unwrap_or_else(tokio::time::Instant::now)), if verify_at.is_some() => {
+                let held: Vec<PathBuf> = open.iter().cloned().collect();
+                let still_open: HashSet<PathBuf> = process::find_openers(&held)
+                    .into_iter()
+                    .map(|process| process.device)
+                    .collect();
+                let stale = held.into_iter().filter(|device| !still_open.contains(device));
+                for device in stale {
+                    tracing::warn!(
+                        "{:?} has been on for a while but nothing has it open, correcting it to off",
+                        device
+                    );
+                    let Some(tx) = own_tx.upgrade() else {
+                        break;
+                    };
+                    let event = monitor::DeviceEvent {
+                        device,
+                        kind: DeviceEventKind::Closed,
+                        process: None,
+                    };
+                    if let Err(e) = tx.try_send(event) {
+                        tracing::error!("error correcting the camera state: {}", e);
+                    }
+                }
+                verify_at = max_on.map(|max_on| tokio::time::Instant::now() + max_on);
+                None
+            }
+            // what's in /dev and /proc has nothing to say about a script's devices
+            Ok(()) = resynced.changed(), if !scripted => {
+                let reason = *resynced.borrow_and_update();
+                tracing::info!("checking the cameras again after {}", reason);
+                #[cfg(target_os = "linux")]
+                match (monitor::resync(&devices, &open), own_tx.upgrade()) {
+                    (Ok(events), Some(tx)) => {
+                        for event in events {
+                            tracing::warn!("missed {:?}", event);
+                            if let Err(e) = tx.try_send(event) {
+                                tracing::error!("error correcting the camera state: {}", e);
+                            }
+                        }
+                    }
+                    (Err(e), _) => tracing::error!("error checking the cameras: {}", e),
+                    (_, None) => {}
+                }
+                // the broker may well have dropped us without the connection noticing yet, put
+                // the state back either way. anything that got corrected follows on its own
+                if let Some(client) = client.as_mut() {
+                    mqtt::send_state(client, &last_state);
+                    mqtt::send_device_states(client, &cameras, &devices, &open);
+                }
+                None
+            }
+            _ = refresh_ticker.tick(), if refresh_interval.is_some() => {
+                // keeps entities with an `expire_after` alive, and puts back whatever a broker
+                // that doesn't keep retained messages has lost
+                let quiet = config.quiet_hours.is_quiet(chrono::Local::now().naive_local());
+                if let Some(client) = client.as_mut() {
+                    if !quiet {
+                        mqtt::send_state(client, &last_state);
+                        mqtt::send_device_states(client, &cameras, &devices, &open);
+                        mqtt::send_application(client, status.borrow().used_by.as_deref());
+                    }
+                    mqtt::send_stats(client, &stats);
+                    let duration = session_start
+                        .map(|start| start.elapsed())
+                        .unwrap_or(last_session);
+                    mqtt::send_session_duration(client, duration);
+                }
+                None
+            }
+            _ = tokio::time::sleep_until(reconcile_until.unwrap_or_else(tokio::time::Instant::now)), if reconcile_until.is_some() => {
+                if let Some(client) = client.as_mut() {
+                    mqtt::reconcile(client, None, &last_state);
+                }
+                reconcile_until = None;
+                None
+            }
+            _ = tokio::time::sleep(config.quiet_hours.until_change(chrono::Local::now().naive_local())), if config.quiet_hours.is_enabled() => {
+                // bring whatever stayed quiet up to date once quiet hours are over
+                if !config.quiet_hours.is_quiet(chrono::Local::now().naive_local()) {
+                    tracing::info!("quiet hours over");
+                    let used_by = status.borrow().used_by.clone();
+                    if let Some(client) = client.as_mut() {
+                        mqtt::send_state(client, &last_state);
+                        mqtt::send_device_states(client, &cameras, &devices, &open);
+                        mqtt::send_application(client, used_by.as_deref());
+                        if let Some(openers) = quiet_attributes.take() {
+                            mqtt::send_attributes(client, &openers);
+                        }
+                        for (camera, at) in quiet_last_used.drain() {
+                            mqtt::send_last_used(client, &camera, at);
+                        }
+                    }
+                    #[cfg(feature = "home-assistant")]
+                    if let Some(home_assistant) = home_assistant.as_ref() {
+                        home_assistant.set_state(&last_state, used_by.as_deref());
+                    }
+                    if let Some(peers) = peers.as_ref() {
+                        peers.set_state(&last_state);
+                    }
+                    #[cfg(feature = "busylight")]
+                    if let Some(busylight) = busylight.as_ref() {
+                        busylight.set_state(&last_state);
+                    }
+                    #[cfg(target_os = "linux")]
+                    if let Some(gpio) = gpio.as_ref() {
+                        gpio.set_state(&last_state);
+                    }
+                } else {
+                    tracing::info!("quiet hours started");
+                }
+                None
+            }
+            _ = tokio::time::sleep(stats::until_midnight()) => {
+                if session_start.is_some() {
+                    stats.add_usage(last_accrued.elapsed());
+                    last_accrued = tokio::time::Instant::now();
+                }
+                if stats.roll_over() {
+                    tracing::info!("reset daily usage stats");
+                }
+                if let Some(client) = client.as_mut() {
+                    mqtt::send_stats(client, &stats);
+                }
+                None
+            }
+            notification = mqtt::poll(&mut eventloop, &mut backoff) => {
+                match notification {
+                    Ok(Event::Incoming(Incoming::ConnAck(ack))) => {
+                        tracing::info!("connected to mqtt: {:?}", ack.code);
+                        backoff.reset();
+                        status.send_modify(|status| status.mqtt_connected = Some(true));
+                        if let Some(brokers) = brokers.as_mut() {
+                            brokers.connected();
+                        }
+                        // a reconnect before the retained state came in loses the subscription,
+                        // so just publish it like any other reconnect
+                        let reconcile = !reconciled;
+                        reconciled = true;
+                        reconcile_until = reconcile
+                            .then(|| tokio::time::Instant::now() + Duration::from_secs(2));
+                        if let Some(client) = client.as_mut() {
+                            mqtt::on_connect(client, &cameras, &devices, &last_state, &open, &stats, reconcile)?;
+                            mqtt::send_application(client, status.borrow().used_by.as_deref());
+                            if let Some(start) = session_start {
+                                mqtt::send_session_duration(client, start.elapsed());
+                            }
+                        }
+                    }
+                    Ok(Event::Incoming(Incoming::Publish(p))) => {
+                        tracing::debug!("received message: {:?}", p);
+                        if let Some(client) = client.as_mut().filter(|client| {
+                            reconcile_until.is_some() && mqtt::is_retained_state(client, &p)
+                        }) {
+                            mqtt::reconcile(client, Some(&p.payload), &last_state);
+                            reconcile_until = None;
+                        }
+                    }
+                    Ok(Event::Incoming(i)) => {
+                        tracing::debug!("received event: {:?}", i);
+                    }
+                    Ok(Event::Outgoing(o)) => {
+                        tracing::debug!("sent event: {:?}", o);
+                        // the event loop took a request, so there's room for more of the backlog
+                        if let Some(client) = client.as_mut() {
+                            client.flush();
+                        }
+                    }
+                    Err(e) => {
+                        #[cfg(feature = "otel")]
+                        otel::MQTT_ERRORS.inc();
+                        status.send_modify(|status| status.mqtt_connected = Some(false));
+                        if let Some(client) = client.as_mut() {
+                            client.disconnected();
+                        }
+                        let failed_over = brokers.as_mut().is_some_and(mqtt::Brokers::failed);
+                        match (brokers.as_ref(), client.as_mut()) {
+                            (Some(brokers), Some(client)) if failed_over => {
+                                tracing::warn!("mqtt connection error: {}", e);
+                                let (new_client, new_eventloop) = brokers.connect();
+                                client.set_client(new_client);
+                                eventloop = Some(new_eventloop);
+                                backoff.reset();
+                                failback_ticker.reset();
+                            }
+                            // never connected yet, make it clear startup isn't stuck on the broker
+                            _ if !reconciled => {
+                                let delay = backoff.failed();
+                                tracing::warn!(
+                                    "mqtt broker not reachable yet, watching the cameras anyway and retrying in {:?}: {}",
+                                    delay, e
+                                );
+                            }
+                            _ => {
+                                let delay = backoff.failed();
+                                tracing::warn!("mqtt connection error, reconnecting in {:?}: {}", delay, e);
+                            }
+                        }
+                    }
+                }
+                None
+            }
+            _ = failback_ticker.tick(), if brokers.as_ref().is_some_and(mqtt::Brokers::on_fallback) => {
+                if let (Some(brokers), Some(client)) = (brokers.as_mut(), client.as_mut()) {
+                    if brokers.primary_reachable().await {
+                        // say goodbye properly so the fallback doesn't keep us online
+                        mqtt::shutdown(client, &mut eventloop).await;
+                        brokers.fail_back();
+                        let (new_client, new_eventloop) = brokers.connect();
+                        client.set_client(new_client);
+                        eventloop = Some(new_eventloop);
+                        backoff.reset();
+                    }
+                }
+                None
+            }
+            signal = signals.recv() => {
+                tracing::info!("received {}, shutting down", signal);
+                break;
+            }
+        };
+
+        let Some(change) = change else {
+            continue;
+        };
+        #[cfg(feature = "otel")]
+        otel::STATE_CHANGES.inc();
+        let span = tracing::info_span!(
+            "state_change",
+            state = ?change.state,
+            device = ?change.device,
+            latency_ms = change.observed.elapsed().as_millis() as u64
+        );
+        let entered = span.enter();
+        // what the backend reported beats a /proc scan this late, which misses opens that are
+        // already over by the time the debounce lets the change through
+        let openers = match change.state {
+            CameraState::On => {
+                let mut known: Vec<_> = open
+                    .iter()
+                    .filter_map(|device| reported.get(device))
+                    .cloned()
+                    .collect();
+                known.sort_by(|a, b| a.device.cmp(&b.device));
+                match known.is_empty() && !scripted {
+                    true => process::find_openers(&devices),
+                    false => known,
+                }
+            }
+            CameraState::Off => Vec::new(),
+        };
+        let used_by = process::describe(&openers);
+
+        match change.state {
+            CameraState::On => stats.session_started(),
+            CameraState::Off => stats.add_usage(last_accrued.elapsed()),
+        }
+        last_accrued = tokio::time::Instant::now();
+
+        // during quiet hours the state is still tracked, but nothing that could light up the
+        // room hears about it
+        let quiet = config
+            .quiet_hours
+            .is_quiet(chrono::Local::now().naive_local());
+
+        match client.as_mut() {
+            Some(client) => {
+                mqtt::send_stats(client, &stats);
+                let duration = match change.state {
+                    CameraState::On => Duration::ZERO,
+                    CameraState::Off => session_start
+                        .map(|start| start.elapsed())
+                        .unwrap_or_default(),
+                };
+                mqtt::send_session_duration(client, duration);
+
+                let camera = cameras.camera(&change.device);
+                if !quiet {
+                    mqtt::send_event(client, &change.state);
+                    mqtt::send_attributes(client, &openers);
+                    mqtt::send_application(client, used_by.as_deref());
+                    mqtt::send_last_used(client, camera, chrono::Utc::now());
+                    mqtt::send_mirrors(
+                        client,
+                        &config.mirrors,
+                        &change.state,
+                        Some(change.device.as_path()),
+                        used_by.as_deref(),
+                    );
+                } else {
+                    quiet_attributes = Some(openers.clone());
+                    quiet_last_used.insert(camera.to_path_buf(), chrono::Utc::now());
+                    if let Some(topic) = &config.quiet_hours.topic {
+                        mqtt::send_quiet_state(client, topic, &change.state);
+                    }
+                }
+            }
+            None => output::print_state(run.output, &change.state)?,
+        }
+        drop(entered);
+
+        #[cfg(feature = "dbus")]
+        if let Some(service) = service.as_ref() {
+            service.set_state(&change.state, used_by.as_deref()).await;
+        }
+        status.send_modify(|status| {
+            status.state = change.state.clone();
+            status.device = Some(change.device.clone());
+            status.used_by = used_by.clone();
+            status.since =
+                Some(chrono::Local::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, false));
+        });
+        let _ = api_events.send(status::Event::new(
+            status::EventKind::StateChanged,
+            change.device.clone(),
+            change.state.clone(),
+            used_by.clone(),
+        ));
+        #[cfg(feature = "sessions")]
+        if let Some(session_log) = session_log.as_mut() {
+            session_log.record(
+                &change.state,
+                Some(change.device.as_path()),
+                used_by.as_deref(),
+            );
+        }
+
+        if !quiet {
+            #[cfg(feature = "home-assistant")]
+            if let Some(home_assistant) = home_assistant.as_ref() {
+                home_assistant.set_state(&change.state, used_by.as_deref());
+            }
+            if let Some(peers) = peers.as_ref() {
+                peers.set_state(&change.state);
+            }
+            #[cfg(feature = "alerts")]
+            if let (Some(alerts), CameraState::On) = (alerts.as_mut(), &change.state) {
+                alerts.camera_on(&change.device, &openers);
+            }
+            #[cfg(feature = "dbus")]
+            if let Some(notifier) = notifier.as_mut().filter(|_| run.desktop_notifications) {
+                notifier.notify(&change.state, used_by.as_deref()).await;
+            }
+            #[cfg(feature = "busylight")]
+            if let Some(busylight) = busylight.as_ref() {
+                busylight.set_state(&change.state);
+            }
+            #[cfg(target_os = "linux")]
+            if let Some(gpio) = gpio.as_ref() {
+                gpio.set_state(&change.state);
+            }
+
+            // a device specific hook in the config wins over the global flag
+            let device_config = config.device(&change.device);
+            let hook = match change.state {
+                CameraState::On => device_config
+                    .and_then(|d| d.on_camera_on.as_ref())
+                    .or(run.on_camera_on.as_ref()),
+                CameraState::Off => device_config
+                    .and_then(|d| d.on_camera_off.as_ref())
+                    .or(run.on_camera_off.as_ref()),
+            };
+            if let Some(hook) = hook {
+                hooks::run(hook, &change.state, Some(change.device.as_path()), &openers);
+            }
+        }
+
+        if let (CameraState::Off, Some(start)) = (&change.state, session_start) {
+            last_session = start.elapsed();
+        }
+        session_start = match change.state {
+            CameraState::On => Some(tokio::time::Instant::now()),
+            CameraState::Off => None,
+        };
+        verify_at = match change.state {
+            CameraState::On => max_on.map(|max_on| tokio::time::Instant::now() + max_on),
+            CameraState::Off => None,
+        };
+        // start counting from the beginning of the session rather than wherever the
+        // interval happened to be
+        session_ticker.reset();
+        last_state = change.state;
+        if let Some(state_file) = &state_file {
+            state_file.save(&last_state, &open, session_start, last_session);
+        }
+    }
+    if session_start.is_some() {
+        stats.add_usage(last_accrued.elapsed());
+        #[cfg(feature = "sessions")]
+        if let Some(session_log) = session_log.as_mut() {
+            session_log.record(&CameraState::Off, None, None);
+        }
+    }
+    if let Some(client) = client.as_mut() {
+        mqtt::shutdown(client, &mut eventloop).await;
+    }
+    if let Some(peers) = peers {
+        peers.shutdown().await;
+    }
+    #[cfg(unix)]
+    if let Some(path) = &args.control_socket {
+        let _ = std::fs::remove_file(path);
+    }
+
+    Ok(())
+}
+
+#[cfg(all(test, feature = "testing"))]
+mod tests {
+    use super::*;
+
+    /// runs `script` through the whole daemon with `--output capture`, everything it published
+    /// comes back in order
+    async fn run_script(script: &str) -> Vec<testing::Published> {
+        run_script_with(script, &[]).await
+    }
+
+    /// `run_script` with `extra` options on the command line
+    async fn run_script_with(script: &str, extra: &[&str]) -> Vec<testing::Published> {
+        let script = testing::ScriptedMonitor::parse(script).unwrap();
+        let capture = testing::Capture::default();
+        let args: Vec<&str> = ["--debounce-duration", "1000"]
+            .into_iter()
+            .chain(extra.iter().copied())
+            .collect();
+
+        Box::pin(testing::run(
+            &args,
+            script.devices(),
+            Box::new(script),
+            testing::Publisher::capture(capture.clone()),
+        ))
+        .await
+        .unwrap();
+
+        capture.publishes()
+    }
+
+    fn payloads<'a>(published: &'a [testing::Published], topic: &str) -> Vec<&'a str> {
+        published
+            .iter()
+            .filter(|published| published.topic == topic)
+            .map(|published| published.payload.as_str())
+            .collect()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn open_goes_through_the_debounce_to_the_state() {
+        // the camera flickers as zoom starts it up, all inside the debounce window
+        let published = run_script(
+            r#"
+{"after_ms": 50, "device": "/dev/video0", "kind": "opened", "process": {"pid": 42, "name": "zoom", "app": "zoom"}}
+{"after_ms": 100, "device": "/dev/video0", "kind": "closed"}
+{"after_ms": 100, "device": "/dev/video0", "kind": "opened", "process": {"pid": 42, "name": "zoom", "app": "zoom"}}
+{"after_ms": 5000}
+"#,
+        )
+        .await;
+
+        let state = payloads(&published, "homeassistant/binary_sensor/officecamera/state");
+        assert_eq!(state, ["OFF", "ON"]);
+        let device_state = payloads(
+            &published,
+            "homeassistant/binary_sensor/officecamera/video0/state",
+        );
+        assert_eq!(device_state.last(), Some(&"ON"));
+
+        let attributes = payloads(
+            &published,
+            "homeassistant/binary_sensor/officecamera/attributes",
+        );
+        let attributes: serde_json::Value =
+            serde_json::from_str(attributes.last().unwrap()).unwrap();
+        assert_eq!(attributes["application"], "zoom");
+        assert_eq!(attributes["processes"][0]["pid"], 42);
+        assert_eq!(
+            payloads(
+                &published,
+                "homeassistant/sensor/officecamera/application/state"
+            )
+            .last(),
+            Some(&"zoom")
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn startup_flicker_publishes_once() {
+        let published = run_script(
+            r#"
+{"after_ms": 50, "device": "/dev/video0", "kind": "opened", "process": {"pid": 42, "name": "zoom"}}
+{"after_ms": 100, "device": "/dev/video0", "kind": "closed"}
+{"after_ms": 100, "device": "/dev/video0", "kind": "opened", "process": {"pid": 42, "name": "zoom"}}
+{"after_ms": 5000}
+"#,
+        )
+        .await;
+
+        // every open and close still fires the event entity, before debouncing
+        let events: Vec<serde_json::Value> = payloads(
+            &published,
+            "homeassistant/event/officecamera/camera_event/state",
+        )
+        .into_iter()
+        .map(|payload| serde_json::from_str(payload).unwrap())
+        .collect();
+        let event_types: Vec<_> = events.iter().map(|event| &event["event_type"]).collect();
+        assert_eq!(
+            event_types,
+            ["camera_opened", "camera_closed", "camera_opened"]
+        );
+        // but the camera only turned on once
+        let triggers = payloads(
+            &published,
+            "homeassistant/device_automation/officecamera/trigger",
+        );
+        assert_eq!(triggers, ["camera_turned_on"]);
+        let sessions = payloads(
+            &published,
+            "homeassistant/sensor/officecamera/sessions_today/state",
+        );
+        assert_eq!(sessions.last(), Some(&"1"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn close_after_the_window_turns_it_off() {
+        let published = run_script(
+            r#"
+{"after_ms": 50, "device": "/dev/video0", "kind": "opened", "process": {"pid": 7, "name": "obs"}}
+{"after_ms": 3000, "device": "/dev/video0", "kind": "closed"}
+{"after_ms": 5000}
+"#,
+        )
+        .await;
+
+        let state = payloads(&published, "homeassistant/binary_sensor/officecamera/state");
+        assert_eq!(state, ["OFF", "ON", "OFF"]);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test(start_paused = true)]
+    async fn control_socket_doesnt_keep_the_daemon_running() {
+        let socket =
+            std::env::temp_dir().join(format!("camera-snitch-{}.sock", host::random_id(8)));
+        let script = r#"
+{"after_ms": 50, "device": "/dev/video0", "kind": "opened"}
+{"after_ms": 5000}
+"#;
+        let published = tokio::time::timeout(
+            Duration::from_secs(60),
+            run_script_with(script, &["--control-socket", socket.to_str().unwrap()]),
+        )
+        .await
+        .expect("the daemon kept running after the script ended");
+
+        let state = payloads(&published, "homeassistant/binary_sensor/officecamera/state");
+        assert_eq!(state, ["OFF", "ON"]);
+    }
+}
@@ -0,0 +1,265 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use tokio::sync::{mpsc, watch};
+
+#[cfg(all(feature = "ebpf", target_os = "linux"))]
+mod ebpf;
+#[cfg(all(feature = "inotify", target_os = "linux"))]
+mod inotify;
+#[cfg(target_os = "macos")]
+mod macos;
+#[cfg(all(feature = "poll", target_os = "linux"))]
+mod poll;
+#[cfg(target_os = "linux")]
+mod streaming;
+#[cfg(target_os = "windows")]
+mod windows;
+
+#[cfg(target_os = "linux")]
+pub use streaming::StreamingFilter;
+
+use crate::process::ProcessInfo;
+
+/// how device open/close activity gets observed
+#[derive(clap::ValueEnum, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Backend {
+    /// inotify watches on the device nodes, instant but needs inotify access to /dev
+    #[cfg(all(feature = "inotify", target_os = "linux"))]
+    Inotify,
+    /// periodically scan /proc/*/fd for open handles, for environments that restrict inotify
+    #[cfg(all(feature = "poll", target_os = "linux"))]
+    Poll,
+    /// trace openat/close with eBPF, exact per-process events but needs CAP_BPF
+    #[cfg(all(feature = "ebpf", target_os = "linux"))]
+    Ebpf,
+    /// poll CoreMediaIO for cameras that are running somewhere
+    #[cfg(target_os = "macos")]
+    Macos,
+    /// poll the CapabilityAccessManager consent store in the registry for apps using the camera
+    #[cfg(target_os = "windows")]
+    Windows,
+}
+
+#[cfg(all(
+    target_os = "linux",
+    not(any(feature = "inotify", feature = "poll", feature = "ebpf"))
+))]
+compile_error!("at least one of the inotify, poll and ebpf features is needed on linux");
+
+impl Default for Backend {
+    fn default() -> Self {
+        #[cfg(all(feature = "inotify", target_os = "linux"))]
+        return Backend::Inotify;
+        #[cfg(all(not(feature = "inotify"), feature = "poll", target_os = "linux"))]
+        return Backend::Poll;
+        #[cfg(all(
+            not(any(feature = "inotify", feature = "poll")),
+            feature = "ebpf",
+            target_os = "linux"
+        ))]
+        return Backend::Ebpf;
+        #[cfg(target_os = "macos")]
+        return Backend::Macos;
+        #[cfg(target_os = "windows")]
+        return Backend::Windows;
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceEventKind {
+    Opened,
+    Closed,
+    /// the device node showed up, e.g. a USB camera got plugged in
+    Added,
+    /// the device node went away
+    Removed,
+}
+
+impl DeviceEventKind {
+    /// `opened`, `closed`, `added` or `removed`, as used in the audit log and event entity
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DeviceEventKind::Opened => "opened",
+            DeviceEventKind::Closed => "closed",
+            DeviceEventKind::Added => "added",
+            DeviceEventKind::Removed => "removed",
+        }
+    }
+}
+
+/// something opened or closed one of the watched devices, or it came or went
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct DeviceEvent {
+    pub device: PathBuf,
+    pub kind: DeviceEventKind,
+    /// the process behind the event, for backends that know it
+    pub process: Option<ProcessInfo>,
+}
+
+#[cfg(target_os = "macos")]
+pub use macos::find_devices;
+#[cfg(target_os = "windows")]
+pub use windows::find_devices;
+
+/// the video devices to watch
+#[cfg(target_os = "linux")]
+pub fn find_devices() -> anyhow::Result<Vec<PathBuf>> {
+    let mut devices = Vec::new();
+    for file in glob::glob("/dev/video*")? {
+        devices.push(file?);
+    }
+
+    Ok(devices)
+}
+
+/// the events it takes to get from `devices` and `open` to what's really there, going by what's in
+/// /dev and who has it open in /proc
+///
+/// for catching up when events might have been missed, like over a suspend
+#[cfg(target_os = "linux")]
+pub fn resync(
+    devices: &[PathBuf],
+    open: &std::collections::HashSet<PathBuf>,
+) -> anyhow::Result<Vec<DeviceEvent>> {
+    let found = find_devices()?;
+    let really_open: std::collections::HashSet<PathBuf> = crate::process::find_openers(&found)
+        .into_iter()
+        .map(|process| process.device)
+        .collect();
+
+    let event = |device: &PathBuf, kind| DeviceEvent {
+        device: device.clone(),
+        kind,
+        process: None,
+    };
+    let mut events = Vec::new();
+    for device in found.iter().filter(|device| !devices.contains(device)) {
+        events.push(event(device, DeviceEventKind::Added));
+    }
+    // removals close whatever had them open on their own
+    for device in devices.iter().filter(|device| !found.contains(device)) {
+        events.push(event(device, DeviceEventKind::Removed));
+    }
+    for device in &found {
+        match (open.contains(device), really_open.contains(device)) {
+            (false, true) => events.push(event(device, DeviceEventKind::Opened)),
+            (true, false) => events.push(event(device, DeviceEventKind::Closed)),
+            _ => {}
+        }
+    }
+
+    Ok(events)
+}
+
+/// whether `path` is one of the nodes `find_devices` would pick up
+#[cfg(all(feature = "inotify", target_os = "linux"))]
+fn is_camera_node(path: &std::path::Path) -> bool {
+    path.parent() == Some(std::path::Path::new("/dev"))
+        && path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with("video"))
+}
+
+impl Backend {
+    /// the monitor behind this backend, watching `devices`
+    ///
+    /// `resync` is `resume::watch`, for backends that lose events to ask for everything to be
+    /// checked again and to hear when it is
+    ///
+    /// not every backend polls, and only inotify has watches to put back or lose events
+    pub fn monitor(
+        self,
+        devices: &[PathBuf],
+        #[cfg_attr(
+            not(any(
+                all(feature = "poll", target_os = "linux"),
+                target_os = "macos",
+                target_os = "windows"
+            )),
+            allow(unused_variables)
+        )]
+        poll_interval: Duration,
+        #[cfg_attr(
+            not(all(feature = "inotify", target_os = "linux")),
+            allow(unused_variables)
+        )]
+        resync: watch::Sender<&'static str>,
+    ) -> Box<dyn DeviceMonitor> {
+        match self {
+            #[cfg(all(feature = "inotify", target_os = "linux"))]
+            Backend::Inotify => Box::new(inotify::InotifyMonitor::new(devices, resync)),
+            #[cfg(all(feature = "poll", target_os = "linux"))]
+            Backend::Poll => Box::new(poll::PollMonitor::new(devices, poll_interval)),
+            #[cfg(all(feature = "ebpf", target_os = "linux"))]
+            Backend::Ebpf => Box::new(ebpf::EbpfMonitor::new(devices)),
+            #[cfg(target_os = "macos")]
+            Backend::Macos => Box::new(macos::MacosMonitor::new(devices, poll_interval)),
+            #[cfg(target_os = "windows")]
+            Backend::Windows => Box::new(windows::WindowsMonitor::new(devices, poll_interval)),
+        }
+    }
+}
+
+/// a source of device open/close events
+///
+/// everything downstream only sees the channel, so a backend (or a fake one) just has to push
+/// `DeviceEvent`s into it
+pub trait DeviceMonitor: Send {
+    /// starts watching in the background, sending events into `tx` until its receiver is dropped
+    fn start(self: Box<Self>, tx: mpsc::Sender<DeviceEvent>) -> anyhow::Result<()>;
+}
+
+/// starts `monitor`, events come out of the returned channel
+///
+/// the sender is handed back too, for feeding in events that don't come from a device
+pub fn start(
+    monitor: Box<dyn DeviceMonitor>,
+) -> anyhow::Result<(mpsc::Sender<DeviceEvent>, mpsc::Receiver<DeviceEvent>)> {
+    let (tx, rx) = mpsc::channel(64);
+    monitor.start(tx.clone())?;
+
+    Ok((tx, rx))
+}
+
+#[cfg(all(test, feature = "testing"))]
+mod tests {
+    use super::*;
+    use crate::testing::ScriptedMonitor;
+
+    #[tokio::test]
+    async fn start_forwards_events_in_order() {
+        let monitor = ScriptedMonitor::parse(
+            r#"
+{"device": "/dev/video0", "kind": "opened"}
+{"device": "/dev/video0", "kind": "closed"}
+"#,
+        )
+        .unwrap();
+        let (own_tx, mut rx) = start(Box::new(monitor)).unwrap();
+        own_tx
+            .send(DeviceEvent {
+                device: PathBuf::from("/dev/video1"),
+                kind: DeviceEventKind::Added,
+                process: None,
+            })
+            .await
+            .unwrap();
+
+        let mut kinds = Vec::new();
+        for _ in 0..3 {
+            let event = rx.recv().await.unwrap();
+            kinds.push((event.device, event.kind));
+        }
+        kinds.sort_by_key(|(device, _)| device.clone());
+        assert_eq!(
+            kinds,
+            [
+                (PathBuf::from("/dev/video0"), DeviceEventKind::Opened),
+                (PathBuf::from("/dev/video0"), DeviceEventKind::Closed),
+                (PathBuf::from("/dev/video1"), DeviceEventKind::Added),
+            ]
+        );
+    }
+}
@@ -0,0 +1,239 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use aya::maps::RingBuf;
+use aya::programs::TracePoint;
+use aya::Ebpf;
+use tokio::io::unix::AsyncFd;
+use tokio::sync::mpsc;
+
+use super::{DeviceEvent, DeviceEventKind, DeviceMonitor};
+use crate::process::ProcessInfo;
+
+/// built from ebpf/camera.bpf.c by build.rs
+static PROGRAM: &[u8] = aya::include_bytes_aligned!(concat!(env!("OUT_DIR"), "/camera.bpf.o"));
+
+const EVENT_OPEN: u32 = 0;
+const EVENT_CLOSE: u32 = 1;
+const EVENT_EXIT: u32 = 2;
+
+/// matches `struct event` in ebpf/camera.bpf.c
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct RawEvent {
+    tgid: u32,
+    minor: u32,
+    kind: u32,
+    comm: [u8; 16],
+}
+
+/// (program, tracepoint category, tracepoint) for everything in the eBPF object
+const TRACEPOINTS: &[(&str, &str, &str)] = &[
+    ("enter_openat", "syscalls", "sys_enter_openat"),
+    ("exit_openat", "syscalls", "sys_exit_openat"),
+    ("enter_close", "syscalls", "sys_enter_close"),
+    ("process_exit", "sched", "sched_process_exit"),
+];
+
+/// loads the tracepoints and forwards opens/closes of `devices` with the pid that did them
+///
+/// this needs CAP_BPF and CAP_PERFMON (or root). unlike inotify every event comes with the process
+/// behind it, even if it only held the device open for a moment
+pub struct EbpfMonitor {
+    devices: Vec<PathBuf>,
+}
+
+impl EbpfMonitor {
+    pub fn new(devices: &[PathBuf]) -> Self {
+        Self {
+            devices: devices.to_vec(),
+        }
+    }
+}
+
+impl DeviceMonitor for EbpfMonitor {
+    fn start(self: Box<Self>, tx: mpsc::Sender<DeviceEvent>) -> anyhow::Result<()> {
+        start(&self.devices, tx)
+    }
+}
+
+fn start(devices: &[PathBuf], tx: mpsc::Sender<DeviceEvent>) -> anyhow::Result<()> {
+    let mut bpf = Ebpf::load(PROGRAM).context("loading eBPF program")?;
+
+    for (name, category, tracepoint) in TRACEPOINTS {
+        let program: &mut TracePoint = bpf
+            .program_mut(name)
+            .with_context(|| format!("eBPF program {} missing", name))?
+            .try_into()?;
+        program.load()?;
+        program
+            .attach(category, tracepoint)
+            .with_context(|| format!("attaching to {}/{}", category, tracepoint))?;
+    }
+
+    let events = RingBuf::try_from(bpf.take_map("EVENTS").context("EVENTS map missing")?)?;
+    let mut events = AsyncFd::new(events)?;
+    let devices = devices.to_vec();
+    tracing::info!("tracing opens of {:?} with eBPF", devices);
+
+    tokio::spawn(async move {
+        // the programs stay attached for as long as this is alive
+        let _bpf = bpf;
+        // devices each process still has open, so they can be closed when it exits
+        let mut open: HashMap<u32, Vec<PathBuf>> = HashMap::new();
+
+        loop {
+            let mut guard = match events.readable_mut().await {
+                Ok(guard) => guard,
+                Err(e) => {
+                    tracing::error!("error waiting on eBPF ring buffer: {}", e);
+                    return;
+                }
+            };
+
+            let mut batch = Vec::new();
+            while let Some(item) = guard.get_inner_mut().next() {
+                if item.len() < std::mem::size_of::<RawEvent>() {
+                    continue;
+                }
+                // SAFETY: the length is checked above and RawEvent is plain old data
+                let raw = unsafe { std::ptr::read_unaligned(item.as_ptr() as *const RawEvent) };
+                batch.extend(translate(&raw, &devices, &mut open));
+            }
+            guard.clear_ready();
+
+            for event in batch {
+                tracing::debug!("eBPF event: {:?}", event);
+                if tx.send(event).await.is_err() {
+                    return;
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// turns a raw event into device events, an exit closes everything the process had open
+///
+/// `open` doubles as a refcount: a device only gets a close once no process holds it anymore,
+/// otherwise one app closing it would turn the camera off under another one that's still using it
+fn translate(
+    raw: &RawEvent,
+    devices: &[PathBuf],
+    open: &mut HashMap<u32, Vec<PathBuf>>,
+) -> Vec<DeviceEvent> {
+    let name_len = raw
+        .comm
+        .iter()
+        .position(|&c| c == 0)
+        .unwrap_or(raw.comm.len());
+    let name = String::from_utf8_lossy(&raw.comm[..name_len]).into_owned();
+    let process = |device: &PathBuf| ProcessInfo {
+        pid: raw.tgid,
+        name: name.clone(),
+        exe: std::fs::read_link(format!("/proc/{}/exe", raw.tgid)).ok(),
+        app: crate::process::sandbox_app(raw.tgid),
+        device: device.clone(),
+    };
+    let closed = |device: PathBuf| DeviceEvent {
+        process: Some(process(&device)),
+        device,
+        kind: DeviceEventKind::Closed,
+    };
+
+    let device = PathBuf::from(format!("/dev/video{}", raw.minor));
+
+    match raw.kind {
+        EVENT_OPEN if devices.contains(&device) => {
+            open.entry(raw.tgid).or_default().push(device.clone());
+            vec![DeviceEvent {
+                process: Some(process(&device)),
+                device,
+                kind: DeviceEventKind::Opened,
+            }]
+        }
+        EVENT_CLOSE if devices.contains(&device) => {
+            if let Some(held) = open.get_mut(&raw.tgid) {
+                if let Some(i) = held.iter().position(|d| *d == device) {
+                    held.swap_remove(i);
+                }
+                if held.is_empty() {
+                    open.remove(&raw.tgid);
+                }
+            }
+            if still_held(open, &device) {
+                Vec::new()
+            } else {
+                vec![closed(device)]
+            }
+        }
+        EVENT_EXIT => {
+            let mut held = open.remove(&raw.tgid).unwrap_or_default();
+            held.sort();
+            held.dedup();
+            held.into_iter()
+                .filter(|device| !still_held(open, device))
+                .map(closed)
+                .collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+fn still_held(open: &HashMap<u32, Vec<PathBuf>>, device: &PathBuf) -> bool {
+    open.values().any(|held| held.contains(device))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn raw(tgid: u32, kind: u32) -> RawEvent {
+        let mut comm = [0u8; 16];
+        comm[..4].copy_from_slice(b"test");
+        RawEvent {
+            tgid,
+            minor: 0,
+            kind,
+            comm,
+        }
+    }
+
+    fn kinds(events: &[DeviceEvent]) -> Vec<DeviceEventKind> {
+        events.iter().map(|e| e.kind).collect()
+    }
+
+    #[test]
+    fn close_waits_for_the_last_process() {
+        let devices = vec![PathBuf::from("/dev/video0")];
+        let mut open = HashMap::new();
+
+        let events = translate(&raw(100, EVENT_OPEN), &devices, &mut open);
+        assert_eq!(kinds(&events), vec![DeviceEventKind::Opened]);
+        let events = translate(&raw(200, EVENT_OPEN), &devices, &mut open);
+        assert_eq!(kinds(&events), vec![DeviceEventKind::Opened]);
+
+        // 200 is still streaming
+        assert!(translate(&raw(100, EVENT_CLOSE), &devices, &mut open).is_empty());
+
+        let events = translate(&raw(200, EVENT_CLOSE), &devices, &mut open);
+        assert_eq!(kinds(&events), vec![DeviceEventKind::Closed]);
+        assert_eq!(events[0].process.as_ref().unwrap().pid, 200);
+    }
+
+    #[test]
+    fn exit_only_closes_what_nobody_else_holds() {
+        let devices = vec![PathBuf::from("/dev/video0")];
+        let mut open = HashMap::new();
+
+        translate(&raw(100, EVENT_OPEN), &devices, &mut open);
+        translate(&raw(200, EVENT_OPEN), &devices, &mut open);
+        assert!(translate(&raw(100, EVENT_EXIT), &devices, &mut open).is_empty());
+
+        let events = translate(&raw(200, EVENT_EXIT), &devices, &mut open);
+        assert_eq!(kinds(&events), vec![DeviceEventKind::Closed]);
+        assert!(open.is_empty());
+    }
+}
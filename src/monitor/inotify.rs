@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use futures_util::StreamExt;
+use tokio::sync::{mpsc, watch};
+
+use super::{DeviceEvent, DeviceEventKind, DeviceMonitor};
+
+/// inotify watches on the device nodes themselves, plus one on `/dev` for cameras coming and
+/// going
+///
+/// when the kernel drops events because the queue overflowed, or drops a watch on a node that's
+/// still there, this asks through `resync` for the cameras to be checked against /proc and puts
+/// all the watches back
+pub struct InotifyMonitor {
+    devices: Vec<PathBuf>,
+    resync: watch::Sender<&'static str>,
+}
+
+impl InotifyMonitor {
+    pub fn new(devices: &[PathBuf], resync: watch::Sender<&'static str>) -> Self {
+        Self {
+            devices: devices.to_vec(),
+            resync,
+        }
+    }
+}
+
+impl DeviceMonitor for InotifyMonitor {
+    fn start(self: Box<Self>, tx: mpsc::Sender<DeviceEvent>) -> anyhow::Result<()> {
+        start(&self.devices, tx, self.resync)
+    }
+}
+
+const DEVICE_MASK: ::inotify::WatchMask =
+    ::inotify::WatchMask::OPEN.union(::inotify::WatchMask::CLOSE);
+const DEV_MASK: ::inotify::WatchMask =
+    ::inotify::WatchMask::CREATE.union(::inotify::WatchMask::DELETE);
+
+fn start(
+    devices: &[PathBuf],
+    tx: mpsc::Sender<DeviceEvent>,
+    resync: watch::Sender<&'static str>,
+) -> anyhow::Result<()> {
+    let mut resynced = resync.subscribe();
+    let notify = ::inotify::Inotify::init()?;
+
+    let mut watches = HashMap::new();
+    for device in devices {
+        tracing::info!("adding watcher for: {:?}", device);
+        let wd = notify.watches().add(device, DEVICE_MASK)?;
+        watches.insert(wd, device.clone());
+    }
+
+    // a node that gets removed takes its watch with it, the new one after a replug needs its own
+    let mut dev = notify.watches().add("/dev", DEV_MASK)?;
+
+    let mut stream = notify.into_event_stream([0u8; 4096])?;
+
+    tokio::spawn(async move {
+        loop {
+            let event = tokio::select! {
+                event = stream.next() => match event {
+                    Some(event) => event,
+                    None => break,
+                },
+                Ok(()) = resynced.changed() => {
+                    let reason = *resynced.borrow_and_update();
+                    // nodes that got recreated in the meantime are new inodes our watches don't
+                    // cover, adding a watch on one that's still the same is a no-op
+                    match stream.watches().add("/dev", DEV_MASK) {
+                        Ok(wd) => dev = wd,
+                        Err(e) => tracing::warn!("can't watch /dev: {}", e),
+                    }
+                    match super::find_devices() {
+                        Ok(found) => {
+                            watches.retain(|_, watched| found.contains(watched));
+                            for device in found {
+                                match stream.watches().add(&device, DEVICE_MASK) {
+                                    Ok(wd) => {
+                                        watches.insert(wd, device);
+                                    }
+                                    Err(e) => tracing::warn!("can't watch {:?}: {}", device, e),
+                                }
+                            }
+                            tracing::info!("re-added watches after {}", reason);
+                        }
+                        Err(e) => tracing::warn!("error listing devices: {}", e),
+                    }
+                    continue;
+                }
+            };
+            let event = match event {
+                Ok(event) => event,
+                Err(e) => {
+                    tracing::error!("error reading inotify events: {}", e);
+                    continue;
+                }
+            };
+            tracing::debug!("inotify event: {:?}", event);
+
+            if event.mask.contains(::inotify::EventMask::Q_OVERFLOW) {
+                tracing::warn!("inotify queue overflowed, events were lost");
+                resync.send_replace("lost inotify events");
+                continue;
+            }
+            if event.mask.contains(::inotify::EventMask::IGNORED) {
+                // the kernel dropped the watch. unplugs do that too and the DELETE on /dev
+                // already took care of those, anything else is a node we stopped hearing about
+                let lost = match event.wd == dev {
+                    true => Some(PathBuf::from("/dev")),
+                    false => watches.remove(&event.wd),
+                };
+                if let Some(path) = lost.filter(|path| path.exists()) {
+                    tracing::warn!("lost the inotify watch on {:?}", path);
+                    resync.send_replace("a lost inotify watch");
+                }
+                continue;
+            }
+
+            if event.wd == dev {
+                let Some(device) = event
+                    .name
+                    .map(|name| Path::new("/dev").join(name))
+                    .filter(|device| super::is_camera_node(device))
+                else {
+                    continue;
+                };
+                let kind = match event.mask {
+                    ::inotify::EventMask::CREATE => {
+                        match stream.watches().add(&device, DEVICE_MASK) {
+                            Ok(wd) => {
+                                watches.insert(wd, device.clone());
+                            }
+                            Err(e) => tracing::warn!("can't watch new {:?}: {}", device, e),
+                        }
+                        DeviceEventKind::Added
+                    }
+                    ::inotify::EventMask::DELETE => {
+                        watches.retain(|_, watched| *watched != device);
+                        DeviceEventKind::Removed
+                    }
+                    _ => continue,
+                };
+
+                let event = DeviceEvent {
+                    device,
+                    kind,
+                    process: None,
+                };
+                if tx.send(event).await.is_err() {
+                    break;
+                }
+                continue;
+            }
+
+            let kind = match event.mask {
+                ::inotify::EventMask::OPEN => DeviceEventKind::Opened,
+                ::inotify::EventMask::CLOSE_NOWRITE | ::inotify::EventMask::CLOSE_WRITE => {
+                    DeviceEventKind::Closed
+                }
+                _ => continue,
+            };
+            let Some(device) = watches.get(&event.wd).cloned() else {
+                continue;
+            };
+
+            let event = DeviceEvent {
+                device,
+                kind,
+                process: None,
+            };
+            if tx.send(event).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    Ok(())
+}
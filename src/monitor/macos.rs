@@ -0,0 +1,264 @@
+//! CoreMediaIO backend, polls each camera's "is running somewhere" property since that's what
+//! flips when any app starts streaming from it
+
+use std::collections::HashMap;
+use std::ffi::{c_char, c_void, CStr};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+
+use super::{DeviceEvent, DeviceEventKind, DeviceMonitor};
+
+type CMIOObjectID = u32;
+type OSStatus = i32;
+type CFStringRef = *const c_void;
+
+#[repr(C)]
+struct CMIOObjectPropertyAddress {
+    selector: u32,
+    scope: u32,
+    element: u32,
+}
+
+const fn fourcc(code: &[u8; 4]) -> u32 {
+    u32::from_be_bytes(*code)
+}
+
+const SYSTEM_OBJECT: CMIOObjectID = 1;
+const HARDWARE_PROPERTY_DEVICES: u32 = fourcc(b"dev#");
+const OBJECT_PROPERTY_NAME: u32 = fourcc(b"lnam");
+const DEVICE_PROPERTY_IS_RUNNING_SOMEWHERE: u32 = fourcc(b"gone");
+const SCOPE_GLOBAL: u32 = fourcc(b"glob");
+const ELEMENT_MAIN: u32 = 0;
+const CF_STRING_ENCODING_UTF8: u32 = 0x0800_0100;
+
+#[link(name = "CoreMediaIO", kind = "framework")]
+extern "C" {
+    fn CMIOObjectGetPropertyDataSize(
+        object: CMIOObjectID,
+        address: *const CMIOObjectPropertyAddress,
+        qualifier_size: u32,
+        qualifier: *const c_void,
+        data_size: *mut u32,
+    ) -> OSStatus;
+    fn CMIOObjectGetPropertyData(
+        object: CMIOObjectID,
+        address: *const CMIOObjectPropertyAddress,
+        qualifier_size: u32,
+        qualifier: *const c_void,
+        data_size: u32,
+        data_used: *mut u32,
+        data: *mut c_void,
+    ) -> OSStatus;
+}
+
+#[link(name = "CoreFoundation", kind = "framework")]
+extern "C" {
+    fn CFStringGetCString(
+        string: CFStringRef,
+        buffer: *mut c_char,
+        buffer_size: isize,
+        encoding: u32,
+    ) -> u8;
+    fn CFRelease(object: *const c_void);
+}
+
+fn address(selector: u32) -> CMIOObjectPropertyAddress {
+    CMIOObjectPropertyAddress {
+        selector,
+        scope: SCOPE_GLOBAL,
+        element: ELEMENT_MAIN,
+    }
+}
+
+/// the ids of every video device CoreMediaIO knows about
+fn device_ids() -> anyhow::Result<Vec<CMIOObjectID>> {
+    let address = address(HARDWARE_PROPERTY_DEVICES);
+    let mut size = 0u32;
+
+    // SAFETY: plain out-parameter calls, the buffer is sized from what the first call reports
+    unsafe {
+        let status =
+            CMIOObjectGetPropertyDataSize(SYSTEM_OBJECT, &address, 0, std::ptr::null(), &mut size);
+        if status != 0 {
+            anyhow::bail!("listing CoreMediaIO devices failed: {}", status);
+        }
+
+        let mut ids = vec![0 as CMIOObjectID; size as usize / std::mem::size_of::<CMIOObjectID>()];
+        let mut used = 0u32;
+        let status = CMIOObjectGetPropertyData(
+            SYSTEM_OBJECT,
+            &address,
+            0,
+            std::ptr::null(),
+            size,
+            &mut used,
+            ids.as_mut_ptr() as *mut c_void,
+        );
+        if status != 0 {
+            anyhow::bail!("listing CoreMediaIO devices failed: {}", status);
+        }
+        ids.truncate(used as usize / std::mem::size_of::<CMIOObjectID>());
+
+        Ok(ids)
+    }
+}
+
+fn device_name(id: CMIOObjectID) -> Option<String> {
+    let address = address(OBJECT_PROPERTY_NAME);
+    let mut name: CFStringRef = std::ptr::null();
+    let mut used = 0u32;
+
+    // SAFETY: the property is a CFStringRef we own and release once copied out
+    unsafe {
+        let status = CMIOObjectGetPropertyData(
+            id,
+            &address,
+            0,
+            std::ptr::null(),
+            std::mem::size_of::<CFStringRef>() as u32,
+            &mut used,
+            &mut name as *mut CFStringRef as *mut c_void,
+        );
+        if status != 0 || name.is_null() {
+            return None;
+        }
+
+        let mut buffer = [0 as c_char; 256];
+        let ok = CFStringGetCString(
+            name,
+            buffer.as_mut_ptr(),
+            buffer.len() as isize,
+            CF_STRING_ENCODING_UTF8,
+        );
+        CFRelease(name);
+        if ok == 0 {
+            return None;
+        }
+
+        Some(
+            CStr::from_ptr(buffer.as_ptr())
+                .to_string_lossy()
+                .into_owned(),
+        )
+    }
+}
+
+fn is_running(id: CMIOObjectID) -> bool {
+    let address = address(DEVICE_PROPERTY_IS_RUNNING_SOMEWHERE);
+    let mut running = 0u32;
+    let mut used = 0u32;
+
+    // SAFETY: the property is a UInt32
+    let status = unsafe {
+        CMIOObjectGetPropertyData(
+            id,
+            &address,
+            0,
+            std::ptr::null(),
+            std::mem::size_of::<u32>() as u32,
+            &mut used,
+            &mut running as *mut u32 as *mut c_void,
+        )
+    };
+
+    status == 0 && running != 0
+}
+
+/// the name we use for a camera, in place of the device path on linux, e.g. `facetime_hd_camera`
+fn device_key(name: &str) -> PathBuf {
+    let key: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    PathBuf::from(key)
+}
+
+/// the cameras to watch, named after what CoreMediaIO calls them
+pub fn find_devices() -> anyhow::Result<Vec<PathBuf>> {
+    Ok(device_ids()?
+        .into_iter()
+        .filter_map(device_name)
+        .map(|name| device_key(&name))
+        .collect())
+}
+
+/// polls CoreMediaIO for whether each camera is running somewhere
+pub struct MacosMonitor {
+    devices: Vec<PathBuf>,
+    interval: Duration,
+}
+
+impl MacosMonitor {
+    pub fn new(devices: &[PathBuf], interval: Duration) -> Self {
+        Self {
+            devices: devices.to_vec(),
+            interval,
+        }
+    }
+}
+
+impl DeviceMonitor for MacosMonitor {
+    fn start(self: Box<Self>, tx: mpsc::Sender<DeviceEvent>) -> anyhow::Result<()> {
+        start(self.devices, self.interval, tx);
+        Ok(())
+    }
+}
+
+fn start(devices: Vec<PathBuf>, interval: Duration, tx: mpsc::Sender<DeviceEvent>) {
+    tracing::info!("polling CoreMediaIO every {:?} for {:?}", interval, devices);
+
+    tokio::task::spawn_blocking(move || {
+        let mut running: HashMap<PathBuf, bool> = HashMap::new();
+
+        loop {
+            // re-list every time so cameras plugged in later still map back to their name
+            let ids = match device_ids() {
+                Ok(ids) => ids,
+                Err(e) => {
+                    tracing::error!("{}", e);
+                    Vec::new()
+                }
+            };
+
+            for id in ids {
+                let Some(device) = device_name(id).map(|name| device_key(&name)) else {
+                    continue;
+                };
+                if !devices.contains(&device) {
+                    continue;
+                }
+
+                let now = is_running(id);
+                let was = running.insert(device.clone(), now).unwrap_or(false);
+                if now == was {
+                    continue;
+                }
+
+                let event = DeviceEvent {
+                    device,
+                    kind: if now {
+                        DeviceEventKind::Opened
+                    } else {
+                        DeviceEventKind::Closed
+                    },
+                    process: None,
+                };
+                tracing::debug!("CoreMediaIO event: {:?}", event);
+                if tx.blocking_send(event).is_err() {
+                    return;
+                }
+            }
+
+            std::thread::sleep(interval);
+        }
+    });
+}
@@ -0,0 +1,96 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+
+use super::{DeviceEvent, DeviceEventKind, DeviceMonitor};
+
+/// scans `/proc` every `interval` and synthesizes an open when a device gains its first user and a
+/// close when it loses its last one, checking which devices are there at all along the way
+pub struct PollMonitor {
+    devices: Vec<PathBuf>,
+    interval: Duration,
+}
+
+impl PollMonitor {
+    pub fn new(devices: &[PathBuf], interval: Duration) -> Self {
+        Self {
+            devices: devices.to_vec(),
+            interval,
+        }
+    }
+}
+
+impl DeviceMonitor for PollMonitor {
+    fn start(self: Box<Self>, tx: mpsc::Sender<DeviceEvent>) -> anyhow::Result<()> {
+        start(self.devices, self.interval, tx);
+        Ok(())
+    }
+}
+
+fn start(devices: Vec<PathBuf>, interval: Duration, tx: mpsc::Sender<DeviceEvent>) {
+    tracing::info!("polling /proc every {:?} for {:?}", interval, devices);
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let mut open = HashSet::new();
+        let mut devices: HashSet<PathBuf> = devices.into_iter().collect();
+
+        loop {
+            ticker.tick().await;
+
+            match super::find_devices() {
+                Ok(found) => {
+                    let found: HashSet<PathBuf> = found.into_iter().collect();
+                    let added = found.difference(&devices).map(|device| DeviceEvent {
+                        device: device.clone(),
+                        kind: DeviceEventKind::Added,
+                        process: None,
+                    });
+                    let removed = devices.difference(&found).map(|device| DeviceEvent {
+                        device: device.clone(),
+                        kind: DeviceEventKind::Removed,
+                        process: None,
+                    });
+                    for event in added.chain(removed).collect::<Vec<_>>() {
+                        tracing::debug!("poll event: {:?}", event);
+                        if tx.send(event).await.is_err() {
+                            return;
+                        }
+                    }
+                    devices = found;
+                }
+                Err(e) => tracing::warn!("error listing devices: {}", e),
+            }
+
+            let scan_devices: Vec<PathBuf> = devices.iter().cloned().collect();
+            let Some(openers) =
+                crate::process::scan(move || crate::process::find_openers(&scan_devices)).await
+            else {
+                continue;
+            };
+            let now_open: HashSet<PathBuf> = openers.into_iter().map(|p| p.device).collect();
+
+            let opened = now_open.difference(&open).map(|device| DeviceEvent {
+                device: device.clone(),
+                kind: DeviceEventKind::Opened,
+                process: None,
+            });
+            let closed = open.difference(&now_open).map(|device| DeviceEvent {
+                device: device.clone(),
+                kind: DeviceEventKind::Closed,
+                process: None,
+            });
+            for event in opened.chain(closed).collect::<Vec<_>>() {
+                tracing::debug!("poll event: {:?}", event);
+                if tx.send(event).await.is_err() {
+                    return;
+                }
+            }
+
+            open = now_open;
+        }
+    });
+}
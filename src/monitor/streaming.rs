@@ -0,0 +1,251 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+use super::{DeviceEvent, DeviceEventKind, DeviceMonitor};
+use crate::process::ProcessInfo;
+
+/// polls a device has to stay open for to count as streaming without being mapped
+const HELD_POLLS: u32 = 5;
+
+/// holds back opens until the camera is actually streaming, so apps that only open it to look at
+/// what it can do (gnome-control-center, browsers listing devices) don't turn it on
+///
+/// wraps another backend and checks every `interval` whether a device that got opened has been
+/// mapped by whoever has it open, see `process::find_streaming`. capture through DMABUF or userptr
+/// never maps the node, so an open that's still there after [`HELD_POLLS`] checks counts too
+pub struct StreamingFilter {
+    inner: Box<dyn DeviceMonitor>,
+    interval: Duration,
+}
+
+impl StreamingFilter {
+    pub fn new(inner: Box<dyn DeviceMonitor>, interval: Duration) -> Self {
+        Self { inner, interval }
+    }
+}
+
+impl DeviceMonitor for StreamingFilter {
+    fn start(self: Box<Self>, tx: mpsc::Sender<DeviceEvent>) -> anyhow::Result<()> {
+        let (inner_tx, rx) = mpsc::channel(64);
+        self.inner.start(inner_tx)?;
+        tokio::spawn(filter(rx, tx, self.interval, Lookup::PROC));
+
+        Ok(())
+    }
+}
+
+/// where the filter finds out who has a device open and who's streaming from it
+#[derive(Clone, Copy)]
+struct Lookup {
+    openers: fn(&[PathBuf]) -> Vec<ProcessInfo>,
+    streaming: fn(&[PathBuf]) -> Vec<ProcessInfo>,
+}
+
+impl Lookup {
+    const PROC: Lookup = Lookup {
+        openers: crate::process::find_openers,
+        streaming: crate::process::find_streaming,
+    };
+
+    /// the openers of `probing` that are streaming, and those of `held` that have had it open for
+    /// long enough that they probably are
+    fn streaming(self, probing: &[PathBuf], held: &[PathBuf]) -> Vec<ProcessInfo> {
+        let mut found = (self.streaming)(probing);
+        for process in (self.openers)(held) {
+            if !found.iter().any(|found| found.device == process.device) {
+                found.push(process);
+            }
+        }
+
+        found
+    }
+}
+
+async fn filter(
+    mut rx: mpsc::Receiver<DeviceEvent>,
+    tx: mpsc::Sender<DeviceEvent>,
+    interval: Duration,
+    lookup: Lookup,
+) {
+    // opened but not streaming yet, and since when
+    let mut probing: HashMap<PathBuf, Instant> = HashMap::new();
+    // streaming, so the open went through
+    let mut streaming: HashSet<PathBuf> = HashSet::new();
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        let event = tokio::select! {
+            event = rx.recv() => match event {
+                Some(event) => event,
+                None => break,
+            },
+            _ = ticker.tick(), if !probing.is_empty() => {
+                let devices: Vec<PathBuf> = probing.keys().cloned().collect();
+                let held: Vec<PathBuf> = probing
+                    .iter()
+                    .filter(|(_, since)| since.elapsed() >= interval * HELD_POLLS)
+                    .map(|(device, _)| device.clone())
+                    .collect();
+                let Some(found) =
+                    crate::process::scan(move || lookup.streaming(&devices, &held)).await
+                else {
+                    continue;
+                };
+
+                for process in found {
+                    if probing.remove(&process.device).is_none() {
+                        continue;
+                    }
+                    tracing::debug!("{:?} is streaming", process.device);
+                    streaming.insert(process.device.clone());
+
+                    let event = DeviceEvent {
+                        device: process.device.clone(),
+                        kind: DeviceEventKind::Opened,
+                        process: Some(process),
+                    };
+                    if tx.send(event).await.is_err() {
+                        return;
+                    }
+                }
+                continue;
+            }
+        };
+
+        match event.kind {
+            DeviceEventKind::Opened if !streaming.contains(&event.device) => {
+                tracing::debug!("{:?} opened, waiting for it to stream", event.device);
+                probing.entry(event.device).or_insert_with(Instant::now);
+                continue;
+            }
+            DeviceEventKind::Closed if probing.contains_key(&event.device) => {
+                // somebody else may still have it open and be about to stream
+                let device = vec![event.device.clone()];
+                let openers = crate::process::scan(move || (lookup.openers)(&device));
+                if openers.await.is_some_and(|openers| openers.is_empty()) {
+                    tracing::debug!("{:?} closed without streaming", event.device);
+                    probing.remove(&event.device);
+                }
+                continue;
+            }
+            DeviceEventKind::Closed | DeviceEventKind::Removed => {
+                probing.remove(&event.device);
+                streaming.remove(&event.device);
+            }
+            DeviceEventKind::Opened | DeviceEventKind::Added => {}
+        }
+
+        if tx.send(event).await.is_err() {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INTERVAL: Duration = Duration::from_millis(20);
+
+    fn process(device: &std::path::Path) -> ProcessInfo {
+        ProcessInfo {
+            pid: 42,
+            name: "zoom".to_string(),
+            exe: None,
+            app: None,
+            device: device.to_path_buf(),
+        }
+    }
+
+    fn everyone(devices: &[PathBuf]) -> Vec<ProcessInfo> {
+        devices.iter().map(|device| process(device)).collect()
+    }
+
+    fn nobody(_: &[PathBuf]) -> Vec<ProcessInfo> {
+        Vec::new()
+    }
+
+    /// a filter over `lookup`, fed through the first channel and read from the second
+    fn start(lookup: Lookup) -> (mpsc::Sender<DeviceEvent>, mpsc::Receiver<DeviceEvent>) {
+        let (inner_tx, inner_rx) = mpsc::channel(8);
+        let (tx, rx) = mpsc::channel(8);
+        tokio::spawn(filter(inner_rx, tx, INTERVAL, lookup));
+        (inner_tx, rx)
+    }
+
+    fn event(kind: DeviceEventKind) -> DeviceEvent {
+        DeviceEvent {
+            device: PathBuf::from("/dev/video0"),
+            kind,
+            process: None,
+        }
+    }
+
+    /// the next event, if there is one within `wait`
+    async fn next(rx: &mut mpsc::Receiver<DeviceEvent>, wait: Duration) -> Option<DeviceEvent> {
+        tokio::time::timeout(wait, rx.recv()).await.ok().flatten()
+    }
+
+    #[tokio::test]
+    async fn mapped_open_counts_once_checked() {
+        let (inner, mut rx) = start(Lookup {
+            openers: everyone,
+            streaming: everyone,
+        });
+        inner.send(event(DeviceEventKind::Opened)).await.unwrap();
+
+        let opened = next(&mut rx, Duration::from_secs(1)).await.unwrap();
+        assert_eq!(opened.kind, DeviceEventKind::Opened);
+        assert_eq!(opened.process.unwrap().pid, 42);
+
+        // the close of a streaming device goes straight through
+        inner.send(event(DeviceEventKind::Closed)).await.unwrap();
+        let closed = next(&mut rx, Duration::from_secs(1)).await.unwrap();
+        assert_eq!(closed.kind, DeviceEventKind::Closed);
+    }
+
+    #[tokio::test]
+    async fn probe_that_closes_is_dropped() {
+        let (inner, mut rx) = start(Lookup {
+            openers: nobody,
+            streaming: nobody,
+        });
+        inner.send(event(DeviceEventKind::Opened)).await.unwrap();
+        inner.send(event(DeviceEventKind::Closed)).await.unwrap();
+
+        assert!(next(&mut rx, INTERVAL * HELD_POLLS * 3).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn unmapped_open_counts_once_it_has_lasted() {
+        // DMABUF capture, held open but never mapped
+        let (inner, mut rx) = start(Lookup {
+            openers: everyone,
+            streaming: nobody,
+        });
+        inner.send(event(DeviceEventKind::Opened)).await.unwrap();
+
+        assert!(next(&mut rx, INTERVAL * 2).await.is_none());
+        let opened = next(&mut rx, Duration::from_secs(1)).await.unwrap();
+        assert_eq!(opened.kind, DeviceEventKind::Opened);
+    }
+
+    #[tokio::test]
+    async fn close_while_someone_else_holds_it_keeps_probing() {
+        let (inner, mut rx) = start(Lookup {
+            openers: everyone,
+            streaming: nobody,
+        });
+        inner.send(event(DeviceEventKind::Opened)).await.unwrap();
+        inner.send(event(DeviceEventKind::Closed)).await.unwrap();
+
+        // the other opener still gets there once it has held it long enough
+        let opened = next(&mut rx, Duration::from_secs(1)).await.unwrap();
+        assert_eq!(opened.kind, DeviceEventKind::Opened);
+    }
+}
@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+use winreg::enums::{HKEY_CURRENT_USER, HKEY_LOCAL_MACHINE, HKEY_USERS};
+use winreg::RegKey;
+
+use super::{DeviceEvent, DeviceEventKind, DeviceMonitor};
+
+/// where windows keeps track of which apps used which capability, one subkey per capability
+const CONSENT_STORE: &str =
+    r"Software\Microsoft\Windows\CurrentVersion\CapabilityAccessManager\ConsentStore";
+
+/// true if the app behind `key` is using the capability right now, windows stamps
+/// `LastUsedTimeStart` when it starts and only fills in `LastUsedTimeStop` once it lets go
+fn app_in_use(key: &RegKey) -> bool {
+    let start = key.get_value::<u64, _>("LastUsedTimeStart").unwrap_or(0);
+    let stop = key.get_value::<u64, _>("LastUsedTimeStop").unwrap_or(0);
+
+    start != 0 && stop == 0
+}
+
+/// every consent store for `capability` there is to read
+///
+/// usage gets recorded per user, so HKCU alone only covers the account we run as. a service
+/// running as another account sees the desktop user's through their hive under HKEY_USERS, and
+/// some of it goes to HKLM
+fn stores(capability: &str) -> Vec<RegKey> {
+    let path = format!(r"{}\{}", CONSENT_STORE, capability);
+    let mut roots = vec![
+        RegKey::predef(HKEY_CURRENT_USER),
+        RegKey::predef(HKEY_LOCAL_MACHINE),
+    ];
+    let users = RegKey::predef(HKEY_USERS);
+    for sid in users.enum_keys().filter_map(Result::ok) {
+        if sid.ends_with("_Classes") {
+            continue;
+        }
+        if let Ok(hive) = users.open_subkey(&sid) {
+            roots.push(hive);
+        }
+    }
+
+    roots
+        .iter()
+        .filter_map(|root| root.open_subkey(&path).ok())
+        .collect()
+}
+
+/// true if any app is using `capability`, e.g. `webcam`, in any of its consent stores
+fn in_use(capability: &str) -> std::io::Result<bool> {
+    let stores = stores(capability);
+    if stores.is_empty() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "no consent store for it",
+        ));
+    }
+
+    for store in &stores {
+        if store_in_use(store)? {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+/// store apps get a subkey each directly under the capability, everything else lives under
+/// `NonPackaged` with the exe path as the key name
+fn store_in_use(store: &RegKey) -> std::io::Result<bool> {
+    for name in store.enum_keys() {
+        let name = name?;
+        let Ok(app) = store.open_subkey(&name) else {
+            continue;
+        };
+
+        if name == "NonPackaged" {
+            for exe in app.enum_keys().filter_map(Result::ok) {
+                if app.open_subkey(&exe).is_ok_and(|key| app_in_use(&key)) {
+                    return Ok(true);
+                }
+            }
+        } else if app_in_use(&app) {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+/// the capabilities to watch, there's no per-camera state in the registry so all cameras show up
+/// as a single `webcam` device
+pub fn find_devices() -> anyhow::Result<Vec<PathBuf>> {
+    Ok(vec![PathBuf::from("webcam")])
+}
+
+/// polls the consent store for apps using each capability
+pub struct WindowsMonitor {
+    devices: Vec<PathBuf>,
+    interval: Duration,
+}
+
+impl WindowsMonitor {
+    pub fn new(devices: &[PathBuf], interval: Duration) -> Self {
+        Self {
+            devices: devices.to_vec(),
+            interval,
+        }
+    }
+}
+
+impl DeviceMonitor for WindowsMonitor {
+    fn start(self: Box<Self>, tx: mpsc::Sender<DeviceEvent>) -> anyhow::Result<()> {
+        start(self.devices, self.interval, tx);
+        Ok(())
+    }
+}
+
+fn start(devices: Vec<PathBuf>, interval: Duration, tx: mpsc::Sender<DeviceEvent>) {
+    tracing::info!(
+        "polling the capability consent store every {:?} for {:?}",
+        interval,
+        devices
+    );
+
+    tokio::task::spawn_blocking(move || {
+        let mut active: HashMap<PathBuf, bool> = HashMap::new();
+
+        loop {
+            for device in &devices {
+                let now = match in_use(&device.to_string_lossy()) {
+                    Ok(now) => now,
+                    Err(e) => {
+                        tracing::error!("error reading consent store for {:?}: {}", device, e);
+                        continue;
+                    }
+                };
+
+                let was = active.insert(device.clone(), now).unwrap_or(false);
+                if now == was {
+                    continue;
+                }
+
+                let event = DeviceEvent {
+                    device: device.clone(),
+                    kind: if now {
+                        DeviceEventKind::Opened
+                    } else {
+                        DeviceEventKind::Closed
+                    },
+                    process: None,
+                };
+                tracing::debug!("consent store event: {:?}", event);
+                if tx.blocking_send(event).is_err() {
+                    return;
+                }
+            }
+
+            std::thread::sleep(interval);
+        }
+    });
+}
@@ -0,0 +1,1497 @@
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use rumqttc::{
+    AsyncClient, ClientError, ConnectionError, Event, EventLoop, Incoming, LastWill, MqttOptions,
+    Outgoing, QoS, Request,
+};
+use tokio::time::Instant;
+
+use crate::cameras::Cameras;
+use crate::config::{MirrorConfig, TopicsConfig};
+use crate::process::ProcessInfo;
+use crate::stats::UsageStats;
+use crate::CameraState;
+
+/// how many publishes can be queued up for the event loop, this has to fit a full round of
+/// discovery since that all gets queued in one go on connect
+pub const REQUEST_CAPACITY: usize = 100;
+
+const STATE_TOPIC: &str = "homeassistant/binary_sensor/{node_id}/state";
+const ATTRIBUTES_TOPIC: &str = "homeassistant/binary_sensor/{node_id}/attributes";
+/// JSON for every process that opens a camera without being on the expected list, not retained
+const SECURITY_ALERT_TOPIC: &str = "homeassistant/binary_sensor/{node_id}/security_alert";
+/// `online` while we're connected, the broker flips it to `offline` through our last will
+const AVAILABILITY_TOPIC: &str = "homeassistant/binary_sensor/{node_id}/availability";
+
+/// exponential backoff with jitter between reconnect attempts
+pub struct Backoff {
+    attempt: u32,
+    max: Duration,
+    retry_at: Option<Instant>,
+}
+
+impl Backoff {
+    pub fn new(max: Duration) -> Self {
+        Self {
+            attempt: 0,
+            max,
+            retry_at: None,
+        }
+    }
+
+    /// schedules the next attempt after a failure, returns how long that is from now
+    pub fn failed(&mut self) -> Duration {
+        let delay = Duration::from_secs(1)
+            .saturating_mul(2u32.saturating_pow(self.attempt))
+            .min(self.max);
+        self.attempt = self.attempt.saturating_add(1);
+
+        // somewhere between half and all of the delay, so a fleet of instances doesn't hammer the
+        // broker in lockstep when it comes back
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or_default();
+        let delay = delay / 2 + (delay / 2).mul_f64(nanos as f64 / 1e9);
+
+        self.retry_at = Some(Instant::now() + delay);
+        delay
+    }
+
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+        self.retry_at = None;
+    }
+}
+
+/// parses a `host` or `host:port` broker address, falling back to `default_port`
+///
+/// IPv6 addresses take a port as `[addr]:port`, a bare one like `::1` is all host
+pub fn parse_broker(broker: &str, default_port: u16) -> anyhow::Result<BrokerAddress> {
+    let port = |port: &str| {
+        port.parse()
+            .with_context(|| format!("invalid port in broker address {}", broker))
+    };
+
+    if let Some(rest) = broker.strip_prefix('[') {
+        let (host, rest) = rest
+            .split_once(']')
+            .with_context(|| format!("missing ] in broker address {}", broker))?;
+        let port = match rest {
+            "" => default_port,
+            rest => match rest.strip_prefix(':') {
+                Some(rest) => port(rest)?,
+                None => anyhow::bail!("expected :port after ] in broker address {}", broker),
+            },
+        };
+        return Ok(BrokerAddress::Tcp(host.to_string(), port));
+    }
+
+    match broker.split_once(':') {
+        Some((host, rest)) if !rest.contains(':') => {
+            Ok(BrokerAddress::Tcp(host.to_string(), port(rest)?))
+        }
+        _ => Ok(BrokerAddress::Tcp(broker.to_string(), default_port)),
+    }
+}
+
+/// where to find a broker
+#[derive(Debug, Clone)]
+pub enum BrokerAddress {
+    Tcp(String, u16),
+    /// a local socket, mosquitto can listen on one for local-only clients
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+impl BrokerAddress {
+    pub fn options(&self, client_id: impl Into<String>) -> MqttOptions {
+        match self {
+            Self::Tcp(host, port) => MqttOptions::new(client_id, host, *port),
+            #[cfg(unix)]
+            Self::Unix(path) => {
+                // rumqttc takes the socket path in place of the host and ignores the port
+                let mut options = MqttOptions::new(client_id, path.display().to_string(), 0);
+                options.set_transport(rumqttc::Transport::unix());
+                options
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for BrokerAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Tcp(host, port) if host.contains(':') => write!(f, "[{}]:{}", host, port),
+            Self::Tcp(host, port) => write!(f, "{}:{}", host, port),
+            #[cfg(unix)]
+            Self::Unix(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// the brokers we can publish to, the first one is the primary and the rest are fallbacks
+///
+/// once the current broker has been unreachable for `failover_after` we move on to the next one.
+/// while on a fallback the primary gets checked every now and then so we can go back to it as soon
+/// as it's up again
+pub struct Brokers {
+    brokers: Vec<(BrokerAddress, MqttOptions)>,
+    current: usize,
+    failover_after: Duration,
+    /// when the current broker first failed since it was last connected
+    down_since: Option<Instant>,
+}
+
+impl Brokers {
+    pub fn new(brokers: Vec<(BrokerAddress, MqttOptions)>, failover_after: Duration) -> Self {
+        Self {
+            brokers,
+            current: 0,
+            failover_after,
+            down_since: None,
+        }
+    }
+
+    /// a client and event loop for the current broker
+    pub fn connect(&self) -> (AsyncClient, EventLoop) {
+        let (address, options) = &self.brokers[self.current];
+        tracing::info!("connecting to mqtt at {}", address);
+
+        AsyncClient::new(options.clone(), REQUEST_CAPACITY)
+    }
+
+    pub fn connected(&mut self) {
+        self.down_since = None;
+    }
+
+    /// call on every connection error, returns true if it's time to switch to the next broker
+    pub fn failed(&mut self) -> bool {
+        if self.brokers.len() < 2 {
+            return false;
+        }
+
+        let down_since = *self.down_since.get_or_insert_with(Instant::now);
+        if down_since.elapsed() < self.failover_after {
+            return false;
+        }
+
+        self.current = (self.current + 1) % self.brokers.len();
+        self.down_since = None;
+        tracing::warn!(
+            "failing over to mqtt broker {}",
+            self.brokers[self.current].0
+        );
+
+        true
+    }
+
+    pub fn on_fallback(&self) -> bool {
+        self.current != 0
+    }
+
+    /// true if the primary accepts connections again, only a connect so it's cheap to check
+    pub async fn primary_reachable(&self) -> bool {
+        let connect = async {
+            match &self.brokers[0].0 {
+                BrokerAddress::Tcp(host, port) => {
+                    tokio::net::TcpStream::connect((host.as_str(), *port))
+                        .await
+                        .map(drop)
+                }
+                #[cfg(unix)]
+                BrokerAddress::Unix(path) => tokio::net::UnixStream::connect(path).await.map(drop),
+            }
+        };
+
+        matches!(
+            tokio::time::timeout(Duration::from_secs(3), connect).await,
+            Ok(Ok(_))
+        )
+    }
+
+    pub fn fail_back(&mut self) {
+        self.current = 0;
+        self.down_since = None;
+        tracing::info!("failing back to mqtt broker {}", self.brokers[0].0);
+    }
+}
+
+/// sets the last will so HA marks everything unavailable if we drop off without saying goodbye
+pub fn set_last_will(options: &mut rumqttc::MqttOptions, topics: &Topics) {
+    options.set_last_will(LastWill::new(
+        &topics.availability,
+        "offline",
+        QoS::AtLeastOnce,
+        true,
+    ));
+}
+
+/// polls the MQTT event loop if there is one, otherwise never resolves
+///
+/// after a connection error this waits out the backoff before polling again, which is what makes
+/// rumqttc reconnect. the deadline lives in `backoff` so being cancelled by `select!` is fine
+pub async fn poll(
+    eventloop: &mut Option<EventLoop>,
+    backoff: &mut Backoff,
+) -> Result<Event, ConnectionError> {
+    match eventloop {
+        Some(eventloop) => {
+            if let Some(retry_at) = backoff.retry_at {
+                tokio::time::sleep_until(retry_at).await;
+                backoff.retry_at = None;
+            }
+            eventloop.poll().await
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// how HA tells that our entities are gone
+#[derive(clap::ValueEnum, Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum AvailabilityMode {
+    /// the availability topic, which the last will flips to `offline` when we drop off
+    #[default]
+    Topic,
+    /// no availability topic, entities go unavailable once they haven't been updated for
+    /// `--expire-after`. for brokers that don't hold on to retained messages
+    Expiry,
+}
+
+/// what goes into the discovery payloads besides the entities themselves
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DiscoveryOptions {
+    pub availability: AvailabilityMode,
+    /// seconds without an update before HA marks an entity unavailable, for the ones we keep
+    /// refreshing
+    pub expire_after: Option<u64>,
+}
+
+/// where a `Publisher`'s publishes end up
+enum Sink {
+    Broker(AsyncClient),
+    /// printed instead, for `--output stdout`
+    Stdout,
+    /// kept in memory, for `--output capture`
+    #[cfg(feature = "testing")]
+    Capture(crate::testing::Capture),
+}
+
+/// wraps the client to hold on to publishes while the broker is unreachable
+pub struct Publisher {
+    client: Sink,
+    connected: bool,
+    /// (topic, retain, payload) waiting to go out, oldest first. while disconnected that's the
+    /// latest payload per retained topic, while connected whatever didn't fit in the request
+    /// channel yet
+    pending: Vec<(String, bool, Vec<u8>)>,
+    /// how many topics `pending` holds while disconnected before the oldest gets dropped
+    capacity: usize,
+    discovery: DiscoveryOptions,
+    topics: Topics,
+}
+
+impl Publisher {
+    pub fn new(client: AsyncClient, capacity: usize) -> Self {
+        Self {
+            client: Sink::Broker(client),
+            connected: false,
+            pending: Vec::new(),
+            capacity,
+            discovery: DiscoveryOptions::default(),
+            topics: Topics::default(),
+        }
+    }
+
+    /// a publisher that prints what it would have published, nothing needs to connect
+    pub fn stdout() -> Self {
+        Self {
+            client: Sink::Stdout,
+            connected: false,
+            pending: Vec::new(),
+            capacity: usize::MAX,
+            discovery: DiscoveryOptions::default(),
+            topics: Topics::default(),
+        }
+    }
+
+    /// a publisher handing everything to `capture`, nothing needs to connect either
+    #[cfg(feature = "testing")]
+    pub fn capture(capture: crate::testing::Capture) -> Self {
+        Self {
+            client: Sink::Capture(capture),
+            ..Self::stdout()
+        }
+    }
+
+    pub fn set_discovery_options(&mut self, options: DiscoveryOptions) {
+        self.discovery = options;
+    }
+
+    pub fn set_topics(&mut self, topics: Topics) {
+        self.topics = topics;
+    }
+
+    /// queues a publish without waiting on it
+    ///
+    /// the event loop is polled from the same task as everything else, so awaiting a full request
+    /// queue while the broker is away would stall device monitoring until it came back. while
+    /// disconnected only the latest payload per retained topic is kept, which is all HA needs to
+    /// converge. events and triggers are about right now, so they get dropped rather than going
+    /// out late looking live
+    fn publish(
+        &mut self,
+        topic: impl Into<String>,
+        retain: bool,
+        payload: impl Into<Vec<u8>>,
+    ) -> Result<(), ClientError> {
+        let (topic, payload) = (topic.into(), payload.into());
+
+        // behind whatever is still queued, an older payload mustn't overwrite a newer one
+        if self.connected && self.pending.is_empty() {
+            return match self.send(topic, retain, payload) {
+                Err(ClientError::TryRequest(Request::Publish(publish))) => {
+                    // the request channel is full, it goes out once the event loop makes room
+                    self.queue(publish.topic, publish.retain, publish.payload.to_vec());
+                    Ok(())
+                }
+                res => res,
+            };
+        }
+        self.queue(topic, retain, payload);
+
+        Ok(())
+    }
+
+    fn send(&self, topic: String, retain: bool, payload: Vec<u8>) -> Result<(), ClientError> {
+        match &self.client {
+            Sink::Broker(client) => client.try_publish(topic, QoS::AtLeastOnce, retain, payload)?,
+            Sink::Stdout => print_publish(&topic, retain, &payload),
+            #[cfg(feature = "testing")]
+            Sink::Capture(capture) => capture.push(topic, retain, &payload),
+        }
+        #[cfg(feature = "otel")]
+        crate::otel::MQTT_PUBLISHES.inc();
+
+        Ok(())
+    }
+
+    fn queue(&mut self, topic: String, retain: bool, payload: Vec<u8>) {
+        if !self.connected {
+            if !retain {
+                tracing::debug!("not holding on to {} while disconnected", topic);
+                return;
+            }
+            self.pending
+                .retain(|(pending, retained, _)| !(*retained && *pending == topic));
+            if !self.pending.is_empty() && self.pending.len() >= self.capacity {
+                let (dropped, _, _) = self.pending.remove(0);
+                tracing::warn!(
+                    "offline queue full, dropping pending publish to {}",
+                    dropped
+                );
+            }
+        }
+        self.pending.push((topic, retain, payload));
+    }
+
+    pub fn disconnected(&mut self) {
+        self.connected = false;
+        // a backlog of events and triggers would be stale by the time we're back
+        self.pending.retain(|(_, retain, _)| *retain);
+    }
+
+    /// swaps in the client for another broker, anything published until it connects gets queued
+    pub fn set_client(&mut self, client: AsyncClient) {
+        self.client = Sink::Broker(client);
+        self.connected = false;
+    }
+
+    /// publishes whatever is queued, oldest first, until the request channel is full. call it
+    /// again as the event loop makes progress and the rest follows
+    pub fn flush(&mut self) {
+        while self.connected && !self.pending.is_empty() {
+            let (topic, retain, payload) = self.pending.remove(0);
+            match self.send(topic, retain, payload) {
+                Err(ClientError::TryRequest(Request::Publish(publish))) => {
+                    self.pending
+                        .insert(0, (publish.topic, publish.retain, publish.payload.to_vec()));
+                    break;
+                }
+                Err(e) => tracing::error!("error publishing queued message: {}", e),
+                Ok(()) => {}
+            }
+        }
+    }
+}
+
+/// marks everything unavailable and disconnects cleanly, the broker only sends the last will when
+/// the connection drops
+pub async fn shutdown(client: &mut Publisher, eventloop: &mut Option<EventLoop>) {
+    if !client.connected {
+        return;
+    }
+
+    let topic = client.topics.availability.clone();
+    if let Err(e) = client.publish(topic, true, "offline") {
+        tracing::error!("error publishing availability: {}", e);
+    }
+    disconnect(client, eventloop).await;
+}
+
+/// disconnects once everything queued so far has gone out
+pub async fn disconnect(client: &mut Publisher, eventloop: &mut Option<EventLoop>) {
+    let Some(eventloop) = eventloop else {
+        return;
+    };
+
+    // whatever didn't fit in the request channel only makes it in as the event loop gets polled
+    let backlog = async {
+        while client.connected && !client.pending.is_empty() {
+            if eventloop.poll().await.is_err() {
+                break;
+            }
+            client.flush();
+        }
+    };
+    if tokio::time::timeout(Duration::from_secs(2), backlog)
+        .await
+        .is_err()
+    {
+        tracing::warn!(
+            "timed out sending {} queued publishes",
+            client.pending.len()
+        );
+    }
+
+    if let Sink::Broker(mqtt) = &client.client {
+        disconnect_client(mqtt, eventloop).await;
+    }
+}
+
+/// `disconnect` for a bare client
+pub async fn disconnect_client(mqtt: &AsyncClient, eventloop: &mut EventLoop) {
+    if let Err(e) = mqtt.try_disconnect() {
+        tracing::error!("error disconnecting from mqtt: {}", e);
+        return;
+    }
+
+    // nothing goes out unless the event loop gets polled
+    let drain = async {
+        loop {
+            match eventloop.poll().await {
+                Ok(Event::Outgoing(Outgoing::Disconnect)) | Err(_) => break,
+                Ok(_) => {}
+            }
+        }
+    };
+    if tokio::time::timeout(Duration::from_secs(2), drain)
+        .await
+        .is_err()
+    {
+        tracing::warn!("timed out disconnecting from mqtt");
+    }
+}
+
+/// connects for a one-off command rather than the daemon, it's up to the caller to `disconnect`
+/// once it's done
+///
+/// `options` shouldn't carry our last will or the daemon's client id, either of those would mark a
+/// running daemon offline
+pub async fn connect_once(options: MqttOptions) -> anyhow::Result<(Publisher, EventLoop)> {
+    let (client, mut eventloop) = AsyncClient::new(options, REQUEST_CAPACITY);
+    let mut client = Publisher::new(client, 0);
+
+    let connect = async {
+        loop {
+            if let Event::Incoming(Incoming::ConnAck(_)) = eventloop.poll().await? {
+                return Ok::<_, ConnectionError>(());
+            }
+        }
+    };
+    tokio::time::timeout(Duration::from_secs(10), connect)
+        .await
+        .context("timed out connecting to mqtt")?
+        .context("connecting to mqtt")?;
+    client.connected = true;
+
+    Ok((client, eventloop))
+}
+
+/// the retained state, attributes and availability, for `status` without a daemon to ask
+///
+/// topics with nothing retained are left out
+pub async fn read_status(
+    client: &mut Publisher,
+    eventloop: &mut EventLoop,
+) -> anyhow::Result<Vec<(&'static str, String)>> {
+    let topics = [
+        ("state", client.topics.state.clone()),
+        ("availability", client.topics.availability.clone()),
+        ("attributes", client.topics.attributes.clone()),
+        ("session duration", client.topics.session_duration.clone()),
+    ];
+    if let Sink::Broker(mqtt) = &client.client {
+        for (_, topic) in &topics {
+            mqtt.try_subscribe(topic, QoS::AtLeastOnce)?;
+        }
+    }
+
+    // retained messages come in right after the subscribe, anything missing by the time the rest
+    // are in just isn't there
+    let mut values = Vec::new();
+    let read = async {
+        while values.len() < topics.len() {
+            if let Event::Incoming(Incoming::Publish(p)) = eventloop.poll().await? {
+                if let Some((name, _)) = topics.iter().find(|(_, topic)| *topic == p.topic) {
+                    values.push((*name, String::from_utf8_lossy(&p.payload).into_owned()));
+                }
+            }
+        }
+        Ok::<_, ConnectionError>(())
+    };
+    match tokio::time::timeout(Duration::from_secs(2), read).await {
+        Ok(res) => res.context("reading from mqtt")?,
+        Err(_) => tracing::debug!("gave up waiting on retained status"),
+    }
+    values.sort_by_key(|(name, _)| topics.iter().position(|(n, _)| n == name));
+
+    Ok(values)
+}
+
+/// clears every retained topic under our discovery prefix and the `[topics]` ones, which makes
+/// HA drop the entities
+///
+/// this goes by what's on the broker rather than what we'd publish, so devices that are gone by
+/// now get cleaned up too. mirror and quiet hours topics are left alone since those belong to
+/// whoever configured them
+pub async fn cleanup(client: &mut Publisher, eventloop: &mut EventLoop) -> anyhow::Result<()> {
+    if let Sink::Broker(mqtt) = &client.client {
+        for filter in client.topics.filters() {
+            mqtt.try_subscribe(filter, QoS::AtLeastOnce)?;
+        }
+    }
+
+    // the retained messages come in a burst after the subscribe, once it goes quiet that's all
+    let mut retained = Vec::new();
+    while let Ok(event) = tokio::time::timeout(Duration::from_secs(1), eventloop.poll()).await {
+        if let Event::Incoming(Incoming::Publish(p)) = event.context("reading from mqtt")? {
+            if p.retain && !p.payload.is_empty() {
+                retained.push(p.topic);
+            }
+        }
+    }
+    // overlapping subscriptions get the same message more than once
+    retained.sort();
+    retained.dedup();
+
+    for topic in retained {
+        tracing::info!("clearing {}", topic);
+        if let Err(e) = client.publish(topic, true, "") {
+            tracing::error!("error clearing retained topic: {}", e);
+        }
+    }
+
+    Ok(())
+}
+
+/// one line per publish, `topic (retained): payload`
+fn print_publish(topic: &str, retain: bool, payload: &[u8]) {
+    let mut stdout = std::io::stdout().lock();
+    let retained = if retain { " (retained)" } else { "" };
+    let res = writeln!(
+        stdout,
+        "{}{}: {}",
+        topic,
+        retained,
+        String::from_utf8_lossy(payload)
+    )
+    .and_then(|()| stdout.flush());
+    if let Err(e) = res {
+        tracing::error!("error printing publish: {}", e);
+    }
+}
+
+/// republishes everything HA needs after (re)connecting: availability, discovery and the current
+/// state, in case the broker lost its retained messages while we were away
+///
+/// with `reconcile` the combined state is left for `reconcile` to publish once we've seen what's
+/// retained
+#[tracing::instrument(skip(client, cameras, devices, open, stats))]
+pub fn on_connect(
+    client: &mut Publisher,
+    cameras: &Cameras,
+    devices: &[PathBuf],
+    state: &CameraState,
+    open: &HashSet<PathBuf>,
+    stats: &UsageStats,
+    reconcile: bool,
+) -> anyhow::Result<()> {
+    client.connected = true;
+
+    let topic = client.topics.availability.clone();
+    if let Err(e) = client.publish(topic, true, "online") {
+        tracing::error!("error publishing availability: {}", e);
+    }
+    write_discovery(client, &cameras.present(devices))?;
+    // reconciling reads back the retained state, so it has to be subscribed to before anything
+    // queued lands on it, and a state queued while offline would only be read back as our own
+    let reconciling = match (reconcile, &client.client) {
+        (true, Sink::Broker(mqtt)) => {
+            mqtt.try_subscribe(&client.topics.state, QoS::AtLeastOnce)?;
+            let state_topic = &client.topics.state;
+            client.pending.retain(|(topic, _, _)| topic != state_topic);
+            true
+        }
+        _ => false,
+    };
+    if !client.pending.is_empty() {
+        tracing::info!("flushing {} queued publishes", client.pending.len());
+    }
+    client.flush();
+    if !reconciling {
+        send_state(client, state);
+    }
+    send_device_states(client, cameras, devices, open);
+    send_stats(client, stats);
+
+    Ok(())
+}
+
+/// publishes the details of a process that opened a camera without being expected to
+#[tracing::instrument(skip(client))]
+pub fn send_security_alert(client: &mut Publisher, process: &ProcessInfo) {
+    let payload = crate::security::alert_payload(process);
+
+    let topic = client.topics.security_alert.clone();
+    if let Err(e) = client.publish(topic, false, payload.to_string()) {
+        tracing::error!("error publishing security alert: {}", e);
+    }
+}
+
+/// publishes the state to the quiet hours topic in place of the usual entities
+#[tracing::instrument(skip(client))]
+pub fn send_quiet_state(client: &mut Publisher, topic: &str, state: &CameraState) {
+    if let Err(e) = client.publish(topic, true, state.as_payload()) {
+        tracing::error!("error publishing quiet hours state: {}", e);
+    }
+}
+
+/// publishes the binary sensor state
+#[tracing::instrument(skip(client))]
+pub fn send_state(client: &mut Publisher, state: &CameraState) {
+    let payload = state.as_payload();
+
+    let topic = client.topics.state.clone();
+    match client.publish(topic, true, payload) {
+        Ok(_) => tracing::info!("published state: {}", payload),
+        Err(e) => tracing::error!("error publishing state: {}", e),
+    }
+}
+
+/// true for the retained copy of our own state, which comes in after `on_connect` with `reconcile`
+pub fn is_retained_state(client: &Publisher, publish: &rumqttc::Publish) -> bool {
+    publish.retain && publish.topic == client.topics.state
+}
+
+/// compares the state retained on the broker with the one we detected and only publishes it if
+/// they differ, `retained` is `None` when there was nothing there
+///
+/// a mismatch means the daemon went away without getting the last change out, like after a crash
+#[tracing::instrument(skip(client, retained))]
+pub fn reconcile(client: &mut Publisher, retained: Option<&[u8]>, state: &CameraState) {
+    if let Sink::Broker(mqtt) = &client.client {
+        if let Err(e) = mqtt.try_unsubscribe(&client.topics.state) {
+            tracing::error!("error unsubscribing from state: {}", e);
+        }
+    }
+
+    match retained {
+        Some(retained) if retained == state.as_payload().as_bytes() => {
+            tracing::info!("retained state {} is up to date", state.as_payload());
+        }
+        Some(retained) => {
+            tracing::warn!(
+                "retained state was {} but the camera is {}, correcting it",
+                String::from_utf8_lossy(retained),
+                state.as_payload()
+            );
+            send_state(client, state);
+        }
+        None => send_state(client, state),
+    }
+}
+
+/// publishes the state of a single camera's binary sensor, this follows the device directly
+/// without any debouncing. `camera` is its first node, see `Cameras`
+#[tracing::instrument(skip(client))]
+pub fn send_device_state(client: &mut Publisher, camera: &Path, state: &CameraState) {
+    let topic = client.topics.device_state(camera);
+    if let Err(e) = client.publish(topic, true, state.as_payload()) {
+        tracing::error!("error publishing device state: {}", e);
+    }
+}
+
+/// marks a camera's entities available or not, depending on whether it's plugged in
+#[tracing::instrument(skip(client))]
+pub fn send_device_availability(client: &mut Publisher, camera: &Path, present: bool) {
+    let payload = if present { "online" } else { "offline" };
+
+    let topic = client.topics.device_availability(camera);
+    if let Err(e) = client.publish(topic, true, payload) {
+        tracing::error!("error publishing device availability: {}", e);
+    }
+}
+
+/// publishes the state of every camera's binary sensor, `open` being the nodes in use
+pub fn send_device_states(
+    client: &mut Publisher,
+    cameras: &Cameras,
+    devices: &[PathBuf],
+    open: &HashSet<PathBuf>,
+) {
+    for camera in cameras.present(devices) {
+        send_device_availability(client, &camera, true);
+        send_device_state(client, &camera, &cameras.state(&camera, open));
+    }
+}
+
+/// publishes which app is using the camera as attributes on the binary sensor, `application`
+/// being the flatpak/container identity where there is one
+#[tracing::instrument(skip(client))]
+pub fn send_attributes(client: &mut Publisher, processes: &[ProcessInfo]) {
+    let payload = serde_json::json!({
+        "application": crate::process::describe(processes),
+        "processes": processes,
+    });
+
+    let topic = client.topics.attributes.clone();
+    if let Err(e) = client.publish(topic, true, payload.to_string()) {
+        tracing::error!("error publishing attributes: {}", e);
+    }
+}
+
+/// publishes the name of the app using the camera, `none` while nothing is
+#[tracing::instrument(skip(client))]
+pub fn send_application(client: &mut Publisher, used_by: Option<&str>) {
+    let topic = client.topics.application.clone();
+    if let Err(e) = client.publish(topic, true, used_by.unwrap_or("none")) {
+        tracing::error!("error publishing active application: {}", e);
+    }
+}
+
+/// publishes a debounced state change, firing the matching device trigger
+#[tracing::instrument(skip(client))]
+pub fn send_event(client: &mut Publisher, state: &CameraState) {
+    send_state(client, state);
+
+    let trigger = match state {
+        CameraState::On => "camera_turned_on",
+        CameraState::Off => "camera_turned_off",
+    };
+    let topic = client.topics.trigger.clone();
+    if let Err(e) = client.publish(topic, false, trigger) {
+        tracing::error!("error publishing device trigger: {}", e);
+    }
+}
+
+/// publishes the mapped payload to each of the configured mirror topics
+#[tracing::instrument(skip(client, mirrors))]
+pub fn send_mirrors(
+    client: &mut Publisher,
+    mirrors: &[MirrorConfig],
+    state: &CameraState,
+    device: Option<&Path>,
+    used_by: Option<&str>,
+) {
+    let device = device.map(|d| d.display().to_string()).unwrap_or_default();
+    let vars = [
+        ("state", state.as_payload()),
+        ("device", device.as_str()),
+        ("process", used_by.unwrap_or_default()),
+    ];
+
+    for mirror in mirrors {
+        let payload = match state {
+            CameraState::On => &mirror.payload_on,
+            CameraState::Off => &mirror.payload_off,
+        };
+        // a JSON payload gets its values escaped, a process name with a quote in it would
+        // break it otherwise
+        let payload = match payload.trim_start().starts_with(['{', '[']) {
+            true => crate::template::render_json(payload, &vars),
+            false => crate::template::render(payload, &vars),
+        };
+
+        if let Err(e) = client.publish(&mirror.topic, mirror.retain, payload) {
+            tracing::error!("error publishing to mirror topic {}: {}", mirror.topic, e);
+        }
+    }
+}
+
+const EVENT_TOPIC: &str = "homeassistant/event/{node_id}/camera_event/state";
+/// not retained, unlike the state topic, so triggers don't fire again whenever HA reconnects
+const TRIGGER_TOPIC: &str = "homeassistant/device_automation/{node_id}/trigger";
+const SESSION_DURATION_TOPIC: &str = "homeassistant/sensor/{node_id}/session_duration/state";
+const APPLICATION_TOPIC: &str = "homeassistant/sensor/{node_id}/application/state";
+const STATS_TOPIC: &str = "homeassistant/sensor/{node_id}/{object_id}/state";
+const DEVICE_STATE_TOPIC: &str = "homeassistant/binary_sensor/{node_id}/{device}/state";
+/// `online` while the device is plugged in, next to the daemon-wide availability
+const DEVICE_AVAILABILITY_TOPIC: &str =
+    "homeassistant/binary_sensor/{node_id}/{device}/availability";
+const LAST_USED_TOPIC: &str = "homeassistant/sensor/{node_id}/{device}_last_used/state";
+
+/// what the discovery topics, unique ids and device identifiers are built from
+const NODE_ID: &str = "officecamera";
+
+/// placeholders every topic template gets
+const GLOBAL_PLACEHOLDERS: &[&str] = &["hostname", "node_id"];
+
+/// the topics everything but the discovery configs goes to, the defaults above unless `[topics]`
+/// in the config says otherwise. the discovery configs stay under `homeassistant/`, that's where
+/// HA looks for them, but under `node_id` like the rest
+///
+/// templates can use `{hostname}` and `{node_id}`, and the per-camera and stats ones `{device}`
+/// for the camera's node, like `video0`, and `{object_id}` for the entity, like `minutes_today`
+/// or `video0_last_used`
+#[derive(Debug, Clone)]
+pub struct Topics {
+    node_id: String,
+    state: String,
+    attributes: String,
+    availability: String,
+    security_alert: String,
+    event: String,
+    trigger: String,
+    session_duration: String,
+    application: String,
+    stats: String,
+    device_state: String,
+    device_availability: String,
+    last_used: String,
+}
+
+impl Default for Topics {
+    fn default() -> Self {
+        Self::build(&TopicsConfig::default())
+    }
+}
+
+impl Topics {
+    pub fn new(config: &TopicsConfig) -> anyhow::Result<Self> {
+        if let Some(node_id) = &config.node_id {
+            check_placeholders(node_id, &["hostname"])?;
+        }
+        // each template along with the placeholders only it gets, one of which it needs so
+        // every entity ends up on a topic of its own
+        let templates = [
+            (&config.state, &[][..]),
+            (&config.attributes, &[]),
+            (&config.availability, &[]),
+            (&config.security_alert, &[]),
+            (&config.event, &[]),
+            (&config.trigger, &[]),
+            (&config.session_duration, &[]),
+            (&config.application, &[]),
+            (&config.stats, &["object_id"]),
+            (&config.device_state, &["device", "object_id"]),
+            (&config.device_availability, &["device", "object_id"]),
+            (&config.last_used, &["device", "object_id"]),
+        ];
+        for (template, per_entity) in templates {
+            let Some(template) = template else {
+                continue;
+            };
+            let allowed: Vec<&str> = GLOBAL_PLACEHOLDERS
+                .iter()
+                .chain(per_entity)
+                .copied()
+                .collect();
+            check_placeholders(template, &allowed)?;
+
+            let used = crate::template::placeholders(template);
+            if !per_entity.is_empty() && !per_entity.iter().any(|name| used.contains(name)) {
+                let needed: Vec<String> = per_entity
+                    .iter()
+                    .map(|name| format!("{{{}}}", name))
+                    .collect();
+                anyhow::bail!(
+                    "topic {} would be the same for every entity, it needs {}",
+                    template,
+                    needed.join(" or ")
+                );
+            }
+        }
+
+        let topics = Self::build(config);
+        // HA ignores discovery topics with anything else in the node id
+        let valid = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '-';
+        if topics.node_id.is_empty() || !topics.node_id.chars().all(valid) {
+            anyhow::bail!(
+                "node id {} can only have letters, digits, _ and - in it",
+                topics.node_id
+            );
+        }
+        for topic in topics.all() {
+            if topic.contains(['+', '#']) {
+                anyhow::bail!("topic {} can't have wildcards in it", topic);
+            }
+        }
+
+        Ok(topics)
+    }
+
+    fn build(config: &TopicsConfig) -> Self {
+        let hostname = crate::host::hostname();
+        let node_id = crate::template::render(
+            config.node_id.as_deref().unwrap_or(NODE_ID),
+            &[("hostname", &hostname)],
+        );
+        let topic = |template: &Option<String>, default: &str| {
+            crate::template::render(
+                template.as_deref().unwrap_or(default),
+                &[("hostname", &hostname), ("node_id", &node_id)],
+            )
+        };
+
+        Self {
+            node_id: node_id.clone(),
+            state: topic(&config.state, STATE_TOPIC),
+            attributes: topic(&config.attributes, ATTRIBUTES_TOPIC),
+            availability: topic(&config.availability, AVAILABILITY_TOPIC),
+            security_alert: topic(&config.security_alert, SECURITY_ALERT_TOPIC),
+            event: topic(&config.event, EVENT_TOPIC),
+            trigger: topic(&config.trigger, TRIGGER_TOPIC),
+            session_duration: topic(&config.session_duration, SESSION_DURATION_TOPIC),
+            application: topic(&config.application, APPLICATION_TOPIC),
+            stats: topic(&config.stats, STATS_TOPIC),
+            device_state: topic(&config.device_state, DEVICE_STATE_TOPIC),
+            device_availability: topic(&config.device_availability, DEVICE_AVAILABILITY_TOPIC),
+            last_used: topic(&config.last_used, LAST_USED_TOPIC),
+        }
+    }
+
+    fn all(&self) -> [&String; 12] {
+        [
+            &self.state,
+            &self.attributes,
+            &self.availability,
+            &self.security_alert,
+            &self.event,
+            &self.trigger,
+            &self.session_duration,
+            &self.application,
+            &self.stats,
+            &self.device_state,
+            &self.device_availability,
+            &self.last_used,
+        ]
+    }
+
+    /// where HA finds the discovery config for an entity, the host level binary sensor goes
+    /// without an object id
+    fn config(&self, component: &str, object_id: Option<&str>) -> String {
+        match object_id {
+            Some(object_id) => format!(
+                "homeassistant/{}/{}/{}/config",
+                component, self.node_id, object_id
+            ),
+            None => format!("homeassistant/{}/{}/config", component, self.node_id),
+        }
+    }
+
+    fn unique_id(&self, object_id: &str) -> String {
+        format!("{}_{}", self.node_id, object_id)
+    }
+
+    fn stats(&self, object_id: &str) -> String {
+        crate::template::render(&self.stats, &[("object_id", object_id)])
+    }
+
+    /// a per-camera topic, the entity's object id being the camera's plus `suffix`
+    fn camera(template: &str, device: &Path, suffix: &str) -> String {
+        let device = object_id(device);
+        let object_id = format!("{}{}", device, suffix);
+
+        crate::template::render(template, &[("device", &device), ("object_id", &object_id)])
+    }
+
+    fn device_state(&self, device: &Path) -> String {
+        Self::camera(&self.device_state, device, "")
+    }
+
+    fn device_availability(&self, device: &Path) -> String {
+        Self::camera(&self.device_availability, device, "")
+    }
+
+    fn last_used(&self, device: &Path) -> String {
+        Self::camera(&self.last_used, device, "_last_used")
+    }
+
+    /// subscriptions matching every topic we could have published on, for `cleanup`. levels
+    /// with a placeholder in them turn into `+`, topics with nothing but placeholders are left
+    /// out since they'd match whatever else is on the broker
+    fn filters(&self) -> Vec<String> {
+        let discovery = format!("homeassistant/+/{}/#", self.node_id);
+        let mut filters: Vec<String> = self
+            .all()
+            .into_iter()
+            .map(|topic| {
+                topic
+                    .split('/')
+                    .map(|level| match level.contains('{') {
+                        true => "+",
+                        false => level,
+                    })
+                    .collect::<Vec<_>>()
+                    .join("/")
+            })
+            .filter(|filter| filter.split('/').any(|level| level != "+"))
+            .chain([discovery])
+            .collect();
+        filters.sort();
+        filters.dedup();
+
+        filters
+    }
+}
+
+/// (object id, name, unit) of the usage statistics sensors
+const STATS_SENSORS: &[(&str, &str, &str)] = &[
+    ("minutes_today", "Camera Minutes Today", "min"),
+    ("sessions_today", "Camera Sessions Today", "sessions"),
+    ("minutes_this_week", "Camera Minutes This Week", "min"),
+    (
+        "sessions_this_week",
+        "Camera Sessions This Week",
+        "sessions",
+    ),
+];
+
+/// fails on a placeholder in `template` that isn't one of `allowed`, it would never get filled in
+fn check_placeholders(template: &str, allowed: &[&str]) -> anyhow::Result<()> {
+    let unknown = crate::template::placeholders(template)
+        .into_iter()
+        .find(|name| !allowed.contains(name));
+    if let Some(name) = unknown {
+        anyhow::bail!(
+            "{} has {{{}}} in it, which isn't filled in there, it can use {}",
+            template,
+            name,
+            allowed
+                .iter()
+                .map(|name| format!("{{{}}}", name))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+
+    Ok(())
+}
+
+/// short id for a device to build topics and unique ids from, `/dev/video0` becomes `video0`
+fn object_id(device: &Path) -> String {
+    device
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| device.display().to_string())
+}
+
+/// availability for a per-camera entity, gone if either the daemon or the camera is
+fn device_availability(topics: &Topics, device: &Path) -> serde_json::Value {
+    serde_json::json!([
+        { "topic": topics.availability },
+        { "topic": topics.device_availability(device) },
+    ])
+}
+
+/// the HA device all of our entities are grouped under
+fn ha_device(topics: &Topics) -> serde_json::Value {
+    serde_json::json!({
+        "identifiers": [topics.node_id],
+        "name": "Office Camera",
+        "sw_version": env!("CARGO_PKG_VERSION"),
+        "model": "camera-snitch",
+        "manufacturer": "Will Eaton <me@wseaton.com>"
+    })
+}
+
+/// a device of its own for each camera, with whatever sysfs knows about the hardware and linked
+/// to the host level one
+fn camera_device(topics: &Topics, device: &Path) -> serde_json::Value {
+    let info = crate::v4l::camera_info(device);
+    let object_id = object_id(device);
+
+    let mut payload = serde_json::json!({
+        "identifiers": [topics.unique_id(&object_id)],
+        "name": info.name.unwrap_or(object_id),
+        "via_device": topics.node_id,
+    });
+    let fields = [
+        ("manufacturer", info.manufacturer),
+        ("model", info.model),
+        ("model_id", info.usb_id),
+        ("serial_number", info.serial),
+    ];
+    for (key, value) in fields {
+        if let Some(value) = value {
+            payload[key] = value.into();
+        }
+    }
+
+    payload
+}
+
+/// `refreshed` entities get republished every `--refresh-interval`, so they're the ones that can
+/// have an `expire_after`
+fn publish_config(
+    client: &mut Publisher,
+    topic: &str,
+    mut payload: serde_json::Value,
+    refreshed: bool,
+) -> anyhow::Result<()> {
+    if client.discovery.availability == AvailabilityMode::Expiry {
+        if let Some(payload) = payload.as_object_mut() {
+            for key in ["availability_topic", "availability", "availability_mode"] {
+                payload.remove(key);
+            }
+        }
+    }
+    if let Some(expire_after) = client.discovery.expire_after.filter(|_| refreshed) {
+        payload["expire_after"] = expire_after.into();
+    }
+    let payload = serde_json::to_string(&payload)?;
+
+    tracing::info!("publishing MQTT discovery paylod to {}", topic);
+    if let Err(e) = client.publish(topic, true, payload) {
+        tracing::error!("error publishing discovery: {}", e);
+    }
+
+    Ok(())
+}
+
+// implment mqtt sensor discovery for homeassistant for our binary sensor
+// https://www.home-assistant.io/docs/mqtt/discovery/
+//
+// the per-camera entities go by each camera's first node, `devices` are those
+#[tracing::instrument(skip(client))]
+pub fn write_discovery(client: &mut Publisher, devices: &[PathBuf]) -> anyhow::Result<()> {
+    // the host level sensor, on while any of the cameras is in use
+    let payload = serde_json::json!({
+        "name": "OfficeCamera",
+        "device": ha_device(&client.topics),
+        "state_topic": client.topics.state,
+        "json_attributes_topic": client.topics.attributes,
+        "availability_topic": client.topics.availability,
+        "device_class": "connectivity",
+        "payload_on": "ON",
+        "payload_off": "OFF",
+    });
+    let topic = client.topics.config("binary_sensor", None);
+    publish_config(client, &topic, payload, true)?;
+
+    // how long the current session has been going, or how long the last one lasted while off
+    let payload = serde_json::json!({
+        "name": "Session Duration",
+        "unique_id": client.topics.unique_id("session_duration"),
+        "device": ha_device(&client.topics),
+        "state_topic": client.topics.session_duration,
+        "availability_topic": client.topics.availability,
+        "device_class": "duration",
+        "unit_of_measurement": "s",
+        "state_class": "measurement",
+    });
+    let topic = client.topics.config("sensor", Some("session_duration"));
+    publish_config(client, &topic, payload, true)?;
+
+    // the app using the camera as a plain state, for dashboards and conditions
+    let payload = serde_json::json!({
+        "name": "Active Application",
+        "unique_id": client.topics.unique_id("application"),
+        "device": ha_device(&client.topics),
+        "state_topic": client.topics.application,
+        "availability_topic": client.topics.availability,
+        "icon": "mdi:application",
+    });
+    let topic = client.topics.config("sensor", Some("application"));
+    publish_config(client, &topic, payload, true)?;
+
+    // every raw open/close, for automations that want to trigger on each one rather than on the
+    // debounced state
+    let payload = serde_json::json!({
+        "name": "Camera Event",
+        "unique_id": client.topics.unique_id("camera_event"),
+        "device": ha_device(&client.topics),
+        "state_topic": client.topics.event,
+        "availability_topic": client.topics.availability,
+        "event_types": ["camera_opened", "camera_closed"],
+    });
+    let topic = client.topics.config("event", Some("camera_event"));
+    publish_config(client, &topic, payload, false)?;
+
+    // device triggers, so automations can be built from the device page
+    for trigger in ["camera_turned_on", "camera_turned_off"] {
+        let payload = serde_json::json!({
+            "automation_type": "trigger",
+            "device": ha_device(&client.topics),
+            "topic": client.topics.trigger,
+            "type": trigger,
+            "subtype": "camera",
+            "payload": trigger,
+        });
+        let topic = client.topics.config("device_automation", Some(trigger));
+        publish_config(client, &topic, payload, false)?;
+    }
+
+    // these reset at midnight/monday, which `total_increasing` treats as a new cycle
+    for (object_id, name, unit) in STATS_SENSORS {
+        let payload = serde_json::json!({
+            "name": name,
+            "unique_id": client.topics.unique_id(object_id),
+            "device": ha_device(&client.topics),
+            "state_topic": client.topics.stats(object_id),
+            "availability_topic": client.topics.availability,
+            "unit_of_measurement": unit,
+            "state_class": "total_increasing",
+        });
+        let topic = client.topics.config("sensor", Some(object_id));
+        publish_config(client, &topic, payload, true)?;
+    }
+
+    // one binary sensor per camera next to the combined one above
+    for device in devices {
+        let object_id = object_id(device);
+        let payload = serde_json::json!({
+            "name": "In Use",
+            "unique_id": client.topics.unique_id(&object_id),
+            "device": camera_device(&client.topics, device),
+            "state_topic": client.topics.device_state(device),
+            "availability": device_availability(&client.topics, device),
+            "availability_mode": "all",
+            "device_class": "connectivity",
+            "payload_on": "ON",
+            "payload_off": "OFF",
+        });
+        let topic = client.topics.config("binary_sensor", Some(&object_id));
+        publish_config(client, &topic, payload, true)?;
+    }
+
+    // when each camera was last used, this is only published on use and retained so it carries
+    // over daemon restarts
+    for device in devices {
+        let object_id = object_id(device);
+        let payload = serde_json::json!({
+            "name": "Last Used",
+            "unique_id": client.topics.unique_id(&format!("{}_last_used", object_id)),
+            "device": camera_device(&client.topics, device),
+            "state_topic": client.topics.last_used(device),
+            "availability": device_availability(&client.topics, device),
+            "availability_mode": "all",
+            "device_class": "timestamp",
+        });
+        let topic = client
+            .topics
+            .config("sensor", Some(&format!("{}_last_used", object_id)));
+        publish_config(client, &topic, payload, false)?;
+    }
+
+    Ok(())
+}
+
+/// fires the event entity for a single device open or close, `transition` being `opened` or
+/// `closed`
+#[tracing::instrument(skip(client))]
+pub fn send_device_event(
+    client: &mut Publisher,
+    transition: &str,
+    device: &Path,
+    process: Option<&str>,
+) {
+    let payload = serde_json::json!({
+        "event_type": format!("camera_{}", transition),
+        "device": device,
+        "process": process,
+    });
+
+    let topic = client.topics.event.clone();
+    if let Err(e) = client.publish(topic, false, payload.to_string()) {
+        tracing::error!("error publishing camera event: {}", e);
+    }
+}
+
+/// marks `device` as last used `at`
+#[tracing::instrument(skip(client))]
+pub fn send_last_used(client: &mut Publisher, device: &Path, at: chrono::DateTime<chrono::Utc>) {
+    let timestamp = at.to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+
+    let topic = client.topics.last_used(device);
+    if let Err(e) = client.publish(topic, true, timestamp) {
+        tracing::error!("error publishing last used timestamp: {}", e);
+    }
+}
+
+/// publishes the daily and weekly usage counters
+#[tracing::instrument(skip_all)]
+pub fn send_stats(client: &mut Publisher, stats: &UsageStats) {
+    let values = [
+        ("minutes_today", stats.minutes_today().to_string()),
+        ("sessions_today", stats.sessions_today().to_string()),
+        ("minutes_this_week", stats.minutes_this_week().to_string()),
+        ("sessions_this_week", stats.sessions_this_week().to_string()),
+    ];
+
+    for (object_id, value) in values {
+        let topic = client.topics.stats(object_id);
+        if let Err(e) = client.publish(topic, true, value) {
+            tracing::error!("error publishing {}: {}", object_id, e);
+        }
+    }
+}
+
+/// publishes the length of the current (or last) camera session
+#[tracing::instrument(skip(client))]
+pub fn send_session_duration(client: &mut Publisher, duration: Duration) {
+    let topic = client.topics.session_duration.clone();
+    if let Err(e) = client.publish(topic, true, duration.as_secs().to_string()) {
+        tracing::error!("error publishing session duration: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tcp(address: BrokerAddress) -> (String, u16) {
+        match address {
+            BrokerAddress::Tcp(host, port) => (host, port),
+            #[cfg(unix)]
+            BrokerAddress::Unix(_) => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn parses_host_and_port() {
+        let parse = |broker| tcp(parse_broker(broker, 1883).unwrap());
+        assert_eq!(parse("broker.lan"), ("broker.lan".into(), 1883));
+        assert_eq!(parse("broker.lan:8883"), ("broker.lan".into(), 8883));
+        assert!(parse_broker("broker.lan:mqtt", 1883).is_err());
+    }
+
+    #[test]
+    fn parses_ipv6_brackets() {
+        let parse = |broker| tcp(parse_broker(broker, 1883).unwrap());
+        assert_eq!(parse("[::1]:8883"), ("::1".into(), 8883));
+        assert_eq!(parse("[fe80::1]"), ("fe80::1".into(), 1883));
+        assert!(parse_broker("[::1", 1883).is_err());
+        assert!(parse_broker("[::1]8883", 1883).is_err());
+        assert_eq!(
+            parse_broker("[::1]:8883", 1883).unwrap().to_string(),
+            "[::1]:8883"
+        );
+    }
+
+    #[test]
+    fn bare_ipv6_is_all_host() {
+        let parse = |broker| tcp(parse_broker(broker, 1883).unwrap());
+        assert_eq!(parse("::1"), ("::1".into(), 1883));
+        assert_eq!(parse("fe80::1:1883"), ("fe80::1:1883".into(), 1883));
+    }
+
+    fn topics(config: TopicsConfig) -> anyhow::Result<Topics> {
+        Topics::new(&config)
+    }
+
+    #[test]
+    fn stats_need_their_object_id() {
+        let stats = |template: &str| {
+            topics(TopicsConfig {
+                stats: Some(template.into()),
+                ..Default::default()
+            })
+        };
+
+        // `{device}` never gets filled in for the stats topics
+        assert!(stats("home/camera/{device}/stats").is_err());
+        assert!(stats("home/camera/stats").is_err());
+        let topics = stats("home/camera/{object_id}").unwrap();
+        assert_eq!(topics.stats("minutes_today"), "home/camera/minutes_today");
+    }
+
+    #[test]
+    fn per_camera_topics_get_device_or_object_id() {
+        let last_used = |template: &str| {
+            topics(TopicsConfig {
+                last_used: Some(template.into()),
+                ..Default::default()
+            })
+        };
+
+        let device = Path::new("/dev/video0");
+        assert_eq!(
+            last_used("cam/{device}/last_used")
+                .unwrap()
+                .last_used(device),
+            "cam/video0/last_used"
+        );
+        assert_eq!(
+            last_used("cam/{object_id}").unwrap().last_used(device),
+            "cam/video0_last_used"
+        );
+        assert!(last_used("cam/last_used").is_err());
+        assert!(last_used("cam/{devcie}/last_used").is_err());
+    }
+
+    #[test]
+    fn host_topics_only_get_hostname_and_node_id() {
+        let state = |template: &str| {
+            topics(TopicsConfig {
+                state: Some(template.into()),
+                ..Default::default()
+            })
+        };
+
+        assert!(state("home/{device}/state").is_err());
+        assert_eq!(
+            state("home/{node_id}/state").unwrap().state,
+            "home/officecamera/state"
+        );
+    }
+
+    #[test]
+    fn node_id_moves_discovery_and_unique_ids() {
+        let topics = topics(TopicsConfig {
+            node_id: Some("desk_cam".into()),
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert_eq!(topics.state, "homeassistant/binary_sensor/desk_cam/state");
+        assert_eq!(
+            topics.config("sensor", Some("minutes_today")),
+            "homeassistant/sensor/desk_cam/minutes_today/config"
+        );
+        assert_eq!(topics.unique_id("application"), "desk_cam_application");
+        assert_eq!(ha_device(&topics)["identifiers"][0], "desk_cam");
+        assert!(topics
+            .filters()
+            .contains(&"homeassistant/+/desk_cam/#".to_string()));
+    }
+
+    #[test]
+    fn node_id_is_checked() {
+        let node_id = |node_id: &str| {
+            topics(TopicsConfig {
+                node_id: Some(node_id.into()),
+                ..Default::default()
+            })
+        };
+
+        assert!(node_id("desk cam").is_err());
+        assert!(node_id("{node_id}").is_err());
+        assert!(node_id("").is_err());
+    }
+}
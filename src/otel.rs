@@ -0,0 +1,135 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use anyhow::Context;
+use opentelemetry::metrics::MeterProvider as _;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::{Protocol, WithExportConfig};
+use opentelemetry_sdk::metrics::{PeriodicReader, SdkMeterProvider};
+use opentelemetry_sdk::trace::{SdkTracerProvider, Tracer};
+use opentelemetry_sdk::Resource;
+use tracing::Subscriber;
+use tracing_opentelemetry::OpenTelemetryLayer;
+use tracing_subscriber::registry::LookupSpan;
+
+/// how often the counters go out, spans go in batches on the SDK's own schedule
+const EXPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// a counter exported as an OTLP monotonic sum, counts whether or not there's an exporter
+pub struct Counter {
+    name: &'static str,
+    description: &'static str,
+    value: AtomicU64,
+}
+
+impl Counter {
+    const fn new(name: &'static str, description: &'static str) -> Self {
+        Self {
+            name,
+            description,
+            value: AtomicU64::new(0),
+        }
+    }
+
+    pub fn inc(&self) {
+        self.value.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+pub static DEVICE_EVENTS: Counter = Counter::new(
+    "camera_snitch.device_events",
+    "device opens, closes, plugs and unplugs seen",
+);
+pub static STATE_CHANGES: Counter = Counter::new(
+    "camera_snitch.state_changes",
+    "state changes that made it through the debounce",
+);
+pub static MQTT_PUBLISHES: Counter = Counter::new(
+    "camera_snitch.mqtt_publishes",
+    "messages handed to the broker connection",
+);
+pub static MQTT_ERRORS: Counter = Counter::new(
+    "camera_snitch.mqtt_errors",
+    "errors on the broker connection",
+);
+
+const COUNTERS: [&Counter; 4] = [
+    &DEVICE_EVENTS,
+    &STATE_CHANGES,
+    &MQTT_PUBLISHES,
+    &MQTT_ERRORS,
+];
+
+/// the providers behind the layer, which export from threads of their own
+pub struct Exporter {
+    traces: SdkTracerProvider,
+    metrics: SdkMeterProvider,
+}
+
+impl Exporter {
+    /// sends whatever is still queued, before the process exits and takes it along
+    pub fn shutdown(self) {
+        if let Err(e) = self.traces.shutdown() {
+            tracing::warn!("error flushing traces: {}", e);
+        }
+        if let Err(e) = self.metrics.shutdown() {
+            tracing::warn!("error flushing counters: {}", e);
+        }
+    }
+}
+
+/// starts exporting spans and counters over OTLP/HTTP to the collector at `endpoint`, like
+/// `http://localhost:4318`
+pub fn layer<S>(endpoint: &str) -> anyhow::Result<(OpenTelemetryLayer<S, Tracer>, Exporter)>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    let endpoint = endpoint.trim_end_matches('/');
+    let resource = Resource::builder()
+        .with_service_name("camera-snitch")
+        .with_attributes([
+            KeyValue::new("service.version", env!("CARGO_PKG_VERSION")),
+            KeyValue::new("host.name", crate::host::hostname()),
+        ])
+        .build();
+
+    let spans = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .with_protocol(Protocol::HttpJson)
+        .with_endpoint(format!("{}/v1/traces", endpoint))
+        .build()
+        .context("setting up the trace exporter")?;
+    let traces = SdkTracerProvider::builder()
+        .with_resource(resource.clone())
+        .with_batch_exporter(spans)
+        .build();
+
+    let counters = opentelemetry_otlp::MetricExporter::builder()
+        .with_http()
+        .with_protocol(Protocol::HttpJson)
+        .with_endpoint(format!("{}/v1/metrics", endpoint))
+        .build()
+        .context("setting up the metrics exporter")?;
+    let metrics = SdkMeterProvider::builder()
+        .with_resource(resource)
+        .with_reader(
+            PeriodicReader::builder(counters)
+                .with_interval(EXPORT_INTERVAL)
+                .build(),
+        )
+        .build();
+    let meter = metrics.meter("camera-snitch");
+    for counter in COUNTERS {
+        meter
+            .u64_observable_counter(counter.name)
+            .with_description(counter.description)
+            .with_unit("1")
+            .with_callback(|observer| observer.observe(counter.value.load(Ordering::Relaxed), &[]))
+            .build();
+    }
+
+    let layer = tracing_opentelemetry::layer().with_tracer(traces.tracer("camera-snitch"));
+
+    Ok((layer, Exporter { traces, metrics }))
+}
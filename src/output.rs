@@ -0,0 +1,74 @@
+use std::io::Write;
+
+use crate::CameraState;
+
+/// where state changes get reported to
+#[derive(clap::ValueEnum, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum OutputMode {
+    /// publish to a homeassistant binary sensor over MQTT
+    Mqtt,
+    /// update a homeassistant binary sensor through its REST API, no broker needed
+    #[cfg(feature = "home-assistant")]
+    HomeAssistant,
+    /// print JSON for a waybar `custom` module with `return-type: json`
+    Waybar,
+    /// print JSON for an i3status-rust `custom` block with `json = true`
+    I3status,
+    /// print every publish the MQTT mode would make instead of connecting to a broker, for
+    /// trying things out
+    Stdout,
+    /// like `stdout`, but every publish gets printed as a JSON line once the daemon stops, for
+    /// checking what a `--script` run published
+    #[cfg(feature = "testing")]
+    Capture,
+}
+
+impl OutputMode {
+    /// whether this mode needs a connection to the MQTT broker
+    pub fn uses_mqtt(self) -> bool {
+        self == OutputMode::Mqtt
+    }
+}
+
+/// renders the status bar JSON block for a state, `None` for modes that don't print anything
+fn render(mode: OutputMode, state: &CameraState) -> Option<serde_json::Value> {
+    let (text, alt) = match state {
+        CameraState::On => ("ON AIR", "on"),
+        CameraState::Off => ("", "off"),
+    };
+
+    match mode {
+        OutputMode::Mqtt | OutputMode::Stdout => None,
+        #[cfg(feature = "home-assistant")]
+        OutputMode::HomeAssistant => None,
+        #[cfg(feature = "testing")]
+        OutputMode::Capture => None,
+        // https://github.com/Alexays/Waybar/wiki/Module:-Custom
+        OutputMode::Waybar => Some(serde_json::json!({
+            "text": text,
+            "alt": alt,
+            "tooltip": format!("camera is {}", alt),
+            "class": alt,
+        })),
+        // https://github.com/greshake/i3status-rust/blob/master/doc/blocks.md#custom
+        OutputMode::I3status => Some(serde_json::json!({
+            "text": text,
+            "state": match state {
+                CameraState::On => "Critical",
+                CameraState::Off => "Idle",
+            },
+        })),
+    }
+}
+
+/// prints one line of JSON to stdout for the bar to pick up
+pub fn print_state(mode: OutputMode, state: &CameraState) -> anyhow::Result<()> {
+    if let Some(block) = render(mode, state) {
+        let mut stdout = std::io::stdout().lock();
+        writeln!(stdout, "{}", serde_json::to_string(&block)?)?;
+        // bars read line by line, so don't let this sit in a buffer
+        stdout.flush()?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,284 @@
+use std::collections::HashMap;
+
+use rumqttc::{AsyncClient, Event, EventLoop, Incoming, LastWill, QoS};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, Instant};
+
+use crate::config::PeersConfig;
+use crate::mqtt::{BrokerAddress, Brokers};
+use crate::CameraState;
+
+/// what a peer last said about itself on `<prefix>/<name>/state`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PeerState {
+    On,
+    Off,
+    /// gone, either it said goodbye or the broker sent its last will
+    Offline,
+}
+
+/// shares our state with the other instances under a common topic prefix and publishes a
+/// combined sensor that's on while any of them has a camera on
+///
+/// this runs on its own connection so it can have a last will of its own, that's how a host that
+/// dies with the camera on drops out of the combined state instead of pinning it on. it fails over
+/// between the brokers and back to the primary like the main connection does
+pub struct Peers {
+    state: watch::Sender<CameraState>,
+    task: JoinHandle<()>,
+}
+
+impl Peers {
+    /// `None` unless `[peers]` has a prefix, `brokers` are the primary and the fallbacks in order
+    pub fn from_config(
+        config: &PeersConfig,
+        brokers: &[BrokerAddress],
+        keep_alive: Duration,
+        failover_after: Duration,
+        failback_interval: Duration,
+    ) -> anyhow::Result<Option<Self>> {
+        let Some(prefix) = config.prefix.clone() else {
+            return Ok(None);
+        };
+        let name = config.name.clone().unwrap_or_else(crate::host::hostname);
+        if name.contains(['/', '+', '#']) {
+            anyhow::bail!("peer name {:?} can't be used in a topic", name);
+        }
+
+        let own_topic = format!("{}/{}/state", prefix, name);
+        let options = brokers
+            .iter()
+            .map(|broker| {
+                let mut options = broker.options(format!("camera-snitch-peer-{}", name));
+                options.set_keep_alive(keep_alive);
+                options.set_last_will(LastWill::new(&own_topic, "offline", QoS::AtLeastOnce, true));
+                (broker.clone(), options)
+            })
+            .collect();
+        let brokers = Brokers::new(options, failover_after);
+        let (client, eventloop) = brokers.connect();
+
+        let (state, state_rx) = watch::channel(CameraState::Off);
+        let peering = Peering {
+            client,
+            brokers,
+            failback_interval,
+            prefix,
+            own_topic,
+            peers: HashMap::new(),
+            combined: None,
+        };
+        let task = tokio::spawn(peering.run(eventloop, state_rx));
+
+        Ok(Some(Self { state, task }))
+    }
+
+    /// our own (debounced) state, for the other instances to see
+    pub fn set_state(&self, state: &CameraState) {
+        self.state.send_replace(state.clone());
+    }
+
+    /// tells the others we're gone and disconnects
+    pub async fn shutdown(self) {
+        drop(self.state);
+        if tokio::time::timeout(Duration::from_secs(3), self.task)
+            .await
+            .is_err()
+        {
+            tracing::warn!("timed out leaving the peers");
+        }
+    }
+}
+
+struct Peering {
+    client: AsyncClient,
+    brokers: Brokers,
+    /// how often to check whether the primary is back while on a fallback
+    failback_interval: Duration,
+    prefix: String,
+    own_topic: String,
+    /// every instance we've heard from, including ourselves
+    peers: HashMap<String, PeerState>,
+    /// what the combined sensor was last published as
+    combined: Option<CameraState>,
+}
+
+impl Peering {
+    async fn run(mut self, eventloop: EventLoop, mut state: watch::Receiver<CameraState>) {
+        let mut eventloop = Some(eventloop);
+        let mut backoff = crate::mqtt::Backoff::new(Duration::from_secs(60));
+        let mut failback_ticker = tokio::time::interval_at(
+            Instant::now() + self.failback_interval,
+            self.failback_interval,
+        );
+        failback_ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            tokio::select! {
+                changed = state.changed() => {
+                    if changed.is_err() {
+                        break;
+                    }
+                    let own = state.borrow_and_update().clone();
+                    self.publish_own(&own);
+                }
+                notification = crate::mqtt::poll(&mut eventloop, &mut backoff) => match notification {
+                    Ok(Event::Incoming(Incoming::ConnAck(_))) => {
+                        tracing::info!("connected to peers under {}", self.prefix);
+                        backoff.reset();
+                        self.brokers.connected();
+                        let topic = format!("{}/+/state", self.prefix);
+                        if let Err(e) = self.client.try_subscribe(topic, QoS::AtLeastOnce) {
+                            tracing::error!("error subscribing to peers: {}", e);
+                        }
+                        let own = state.borrow().clone();
+                        self.publish_own(&own);
+                        self.write_discovery();
+                    }
+                    Ok(Event::Incoming(Incoming::Publish(p))) => {
+                        self.observe(&p.topic, &p.payload);
+                    }
+                    Ok(_) => {}
+                    Err(e) if self.brokers.failed() => {
+                        tracing::warn!("peer connection error: {}", e);
+                        eventloop = Some(self.connect());
+                        backoff.reset();
+                        failback_ticker.reset();
+                    }
+                    Err(e) => {
+                        let delay = backoff.failed();
+                        tracing::warn!("peer connection error, reconnecting in {:?}: {}", delay, e);
+                    }
+                },
+                _ = failback_ticker.tick(), if self.brokers.on_fallback() => {
+                    if self.brokers.primary_reachable().await {
+                        // the others on the fallback would otherwise keep seeing us as we were
+                        if let Some(eventloop) = eventloop.as_mut() {
+                            self.leave(eventloop).await;
+                        }
+                        self.brokers.fail_back();
+                        eventloop = Some(self.connect());
+                        backoff.reset();
+                    }
+                }
+            }
+        }
+
+        if let Some(eventloop) = eventloop.as_mut() {
+            self.leave(eventloop).await;
+        }
+    }
+
+    /// a client for the current broker, the event loop is the caller's to poll
+    fn connect(&mut self) -> EventLoop {
+        let (client, eventloop) = self.brokers.connect();
+        self.client = client;
+        eventloop
+    }
+
+    /// tells the others on the current broker we're gone and disconnects
+    async fn leave(&self, eventloop: &mut EventLoop) {
+        if let Err(e) = self
+            .client
+            .try_publish(&self.own_topic, QoS::AtLeastOnce, true, "offline")
+        {
+            tracing::error!("error leaving the peers: {}", e);
+        }
+        crate::mqtt::disconnect_client(&self.client, eventloop).await;
+    }
+
+    fn publish_own(&self, state: &CameraState) {
+        if let Err(e) =
+            self.client
+                .try_publish(&self.own_topic, QoS::AtLeastOnce, true, state.as_payload())
+        {
+            tracing::error!("error publishing to peers: {}", e);
+        }
+    }
+
+    /// takes in a peer's state, updating the combined sensor if that changes it
+    fn observe(&mut self, topic: &str, payload: &[u8]) {
+        let Some(name) = topic
+            .strip_prefix(&self.prefix)
+            .and_then(|topic| topic.strip_prefix('/'))
+            .and_then(|topic| topic.strip_suffix("/state"))
+        else {
+            return;
+        };
+        let state = match payload {
+            b"ON" => PeerState::On,
+            b"OFF" => PeerState::Off,
+            // an empty retained payload is someone clearing the topic, as good as gone
+            _ => PeerState::Offline,
+        };
+        tracing::debug!("peer {} is {:?}", name, state);
+
+        let new_peer = self.peers.insert(name.to_string(), state).is_none();
+        // the combined sensor's availability lists every peer, so it needs updating for new ones
+        if new_peer {
+            self.write_discovery();
+        }
+
+        let combined = match self.peers.values().any(|state| *state == PeerState::On) {
+            true => CameraState::On,
+            false => CameraState::Off,
+        };
+        if self.combined.as_ref() != Some(&combined) {
+            tracing::info!("combined peer state: {}", combined.as_payload());
+            let topic = format!("{}/combined", self.prefix);
+            if let Err(e) =
+                self.client
+                    .try_publish(topic, QoS::AtLeastOnce, true, combined.as_payload())
+            {
+                tracing::error!("error publishing combined state: {}", e);
+            }
+            self.combined = Some(combined);
+        }
+    }
+
+    /// discovery for the combined sensor, every instance publishes the same one
+    ///
+    /// it's available while any of the peers is online, so it can't get stuck on once the last
+    /// one is gone
+    fn write_discovery(&self) {
+        let mut names: Vec<&String> = self.peers.keys().collect();
+        names.sort();
+        let availability: Vec<_> = names
+            .into_iter()
+            .map(|name| {
+                serde_json::json!({
+                    "topic": format!("{}/{}/state", self.prefix, name),
+                    "value_template": "{{ 'offline' if value == 'offline' or value == '' else 'online' }}",
+                })
+            })
+            .collect();
+
+        let mut payload = serde_json::json!({
+            "name": "On Camera Anywhere",
+            "unique_id": "camera_snitch_peers_combined",
+            "device": {
+                "identifiers": ["camera_snitch_peers"],
+                "name": "Camera Snitch",
+                "manufacturer": "Will Eaton <me@wseaton.com>",
+            },
+            "state_topic": format!("{}/combined", self.prefix),
+            "device_class": "connectivity",
+            "payload_on": "ON",
+            "payload_off": "OFF",
+        });
+        // an empty list would make HA treat it as unavailable for good
+        if !availability.is_empty() {
+            payload["availability"] = availability.into();
+            payload["availability_mode"] = "any".into();
+        }
+
+        let topic = "homeassistant/binary_sensor/camera_snitch_peers/combined/config";
+        if let Err(e) = self
+            .client
+            .try_publish(topic, QoS::AtLeastOnce, true, payload.to_string())
+        {
+            tracing::error!("error publishing peer discovery: {}", e);
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+
+use crate::CameraState;
+
+/// down for longer than this and whatever has the camera open now is a new call, not the one
+/// that was running when the daemon stopped
+const MAX_DOWNTIME: Duration = Duration::from_secs(10 * 60);
+
+/// what the daemon had published when it last saved. the usage counters aren't in here, they
+/// have the `--stats-file` of their own, which defaults to [`stats_file`]
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Saved {
+    pub state: CameraState,
+    /// devices that were open
+    pub open: HashSet<PathBuf>,
+    /// when the running session started, `None` while the camera is off
+    pub session_start: Option<DateTime<Utc>>,
+    /// how long the last finished session was, in seconds
+    pub last_session: u64,
+    pub saved_at: Option<DateTime<Utc>>,
+}
+
+impl Saved {
+    /// the start of the running session on tokio's clock, which a scripted run pauses
+    pub fn session_instant(&self) -> Option<Instant> {
+        let running = (Utc::now() - self.session_start?)
+            .to_std()
+            .unwrap_or_default();
+        Instant::now().checked_sub(running)
+    }
+
+    /// the camera got closed while the daemon was down, the session lasted until the last save
+    /// as far as anyone can tell
+    pub fn end_session(&mut self) {
+        if let (Some(start), Some(saved_at)) = (self.session_start.take(), self.saved_at) {
+            self.last_session = (saved_at - start).num_seconds().max(0) as u64;
+        }
+        self.state = CameraState::Off;
+        self.open.clear();
+    }
+}
+
+/// where the usage counters go when there's a `--state-file` but no `--stats-file`,
+/// `state.json` keeps them in `state.stats.json` next to it
+pub fn stats_file(state_file: &Path) -> PathBuf {
+    state_file.with_extension("stats.json")
+}
+
+/// keeps the published state and the running session on disk, so restarting the daemon in the
+/// middle of a call carries on with the same session instead of counting a new one
+pub struct StateFile {
+    path: PathBuf,
+}
+
+impl StateFile {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+        }
+    }
+
+    /// what was saved, as long as it's recent enough to still be the same call, the daemon
+    /// checks it against what's really open
+    pub fn load(&self) -> Option<Saved> {
+        let saved: Saved = match std::fs::read_to_string(&self.path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .map_err(|e| tracing::warn!("ignoring unreadable state file: {}", e))
+                .ok()?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return None,
+            Err(e) => {
+                tracing::warn!("ignoring unreadable state file: {}", e);
+                return None;
+            }
+        };
+
+        let down = saved
+            .saved_at
+            .and_then(|at| (Utc::now() - at).to_std().ok())
+            .unwrap_or_default();
+        if saved.state == CameraState::On && down > MAX_DOWNTIME {
+            tracing::info!(
+                "not picking up the last session, the daemon was down for {:?}",
+                down
+            );
+            return Some(Saved {
+                last_session: saved.last_session,
+                ..Default::default()
+            });
+        }
+
+        Some(saved)
+    }
+
+    pub fn save(
+        &self,
+        state: &CameraState,
+        open: &HashSet<PathBuf>,
+        session_start: Option<Instant>,
+        last_session: Duration,
+    ) {
+        let now = Utc::now();
+        let saved = Saved {
+            state: state.clone(),
+            open: open.clone(),
+            session_start: session_start.and_then(|start| {
+                chrono::Duration::from_std(start.elapsed())
+                    .ok()
+                    .map(|running| now - running)
+            }),
+            last_session: last_session.as_secs(),
+            saved_at: Some(now),
+        };
+
+        // write it next to the real one and swap it in, a crash halfway through shouldn't lose
+        // the session
+        let tmp = PathBuf::from(format!("{}.tmp", self.path.display()));
+        let res = serde_json::to_string(&saved)
+            .map_err(anyhow::Error::from)
+            .and_then(|contents| Ok(std::fs::write(&tmp, contents)?))
+            .and_then(|_| Ok(std::fs::rename(&tmp, &self.path)?));
+        if let Err(e) = res {
+            tracing::error!("error saving state file {}: {}", self.path.display(), e);
+        }
+    }
+}
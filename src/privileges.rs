@@ -0,0 +1,50 @@
+use anyhow::Context;
+use caps::{CapSet, Capability, CapsHashSet};
+use nix::unistd::{Group, User};
+
+/// what we still need once everything is set up: reading other users' `/proc/<pid>/fd` and
+/// `/proc/<pid>/exe` for process attribution
+const KEEP: &[Capability] = &[Capability::CAP_DAC_READ_SEARCH, Capability::CAP_SYS_PTRACE];
+
+/// switches to `user` (and `group`, or the user's primary group) keeping only [`KEEP`]
+///
+/// this has to run after the watches and anything else that needs root are set up, files that get
+/// reopened later like the stats file have to be writable by the new user
+///
+/// capabilities are per thread. glibc applies the setuid to every thread, but only the calling one
+/// keeps [`KEEP`], so anything that needs them afterwards runs on this thread or on one started
+/// after the drop, see [`crate::process::scan`]
+pub fn drop_to(user: &str, group: Option<&str>) -> anyhow::Result<()> {
+    let user = User::from_name(user)?.with_context(|| format!("no such user {}", user))?;
+    let gid = match group {
+        Some(group) => {
+            Group::from_name(group)?
+                .with_context(|| format!("no such group {}", group))?
+                .gid
+        }
+        None => user.gid,
+    };
+
+    // hold on to the permitted set across setuid, we trim it down below
+    nix::sys::prctl::set_keepcaps(true).context("setting keepcaps")?;
+    nix::unistd::setgroups(&[gid]).context("dropping supplementary groups")?;
+    nix::unistd::setgid(gid).context("setting gid")?;
+    nix::unistd::setuid(user.uid).context("setting uid")?;
+    nix::sys::prctl::set_keepcaps(false).context("clearing keepcaps")?;
+
+    let keep: CapsHashSet = KEEP.iter().copied().collect();
+    caps::set(None, CapSet::Permitted, &keep).context("trimming permitted capabilities")?;
+    caps::set(None, CapSet::Effective, &keep).context("raising effective capabilities")?;
+    caps::clear(None, CapSet::Inheritable)?;
+    caps::clear(None, CapSet::Ambient)?;
+    nix::sys::prctl::set_no_new_privs().context("setting no_new_privs")?;
+
+    tracing::info!(
+        "dropped privileges to uid {} gid {}, keeping {:?}",
+        user.uid,
+        gid,
+        KEEP
+    );
+
+    Ok(())
+}
@@ -0,0 +1,215 @@
+use std::path::{Path, PathBuf};
+
+/// a process that has one of the watched devices open
+#[derive(serde::Serialize, Debug, PartialEq, Eq, Clone)]
+pub struct ProcessInfo {
+    pub pid: u32,
+    /// short command name from `/proc/<pid>/comm`
+    pub name: String,
+    /// full path of the executable, if we're allowed to read it
+    pub exe: Option<PathBuf>,
+    /// flatpak/snap app id or container the process runs in, see [`sandbox_app`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub app: Option<String>,
+    pub device: PathBuf,
+}
+
+impl ProcessInfo {
+    /// what to call the process, sandboxed apps go by their app id rather than the wrapper
+    pub fn display_name(&self) -> &str {
+        self.app.as_deref().unwrap_or(&self.name)
+    }
+
+    /// true if the process or its app id is on the `expected` list
+    pub fn is_expected(&self, expected: &[String]) -> bool {
+        expected
+            .iter()
+            .any(|e| *e == self.name || Some(e.as_str()) == self.app.as_deref())
+    }
+}
+
+/// scans `/proc/*/fd` for handles to any of `devices`
+///
+/// this needs enough privileges to read other users' fd tables, processes we can't inspect are
+/// skipped rather than treated as an error
+pub fn find_openers(devices: &[PathBuf]) -> Vec<ProcessInfo> {
+    let mut openers = Vec::new();
+
+    let Ok(procs) = std::fs::read_dir("/proc") else {
+        return openers;
+    };
+
+    for entry in procs.flatten() {
+        let Some(pid) = entry
+            .file_name()
+            .to_str()
+            .and_then(|p| p.parse::<u32>().ok())
+        else {
+            continue;
+        };
+
+        if let Some(device) = open_device(&entry.path(), devices) {
+            let name = std::fs::read_to_string(entry.path().join("comm"))
+                .map(|comm| comm.trim_end().to_string())
+                .unwrap_or_else(|_| format!("pid {}", pid));
+
+            let exe = std::fs::read_link(entry.path().join("exe")).ok();
+
+            openers.push(ProcessInfo {
+                pid,
+                name,
+                exe,
+                app: sandbox_app(pid),
+                device,
+            });
+        }
+    }
+
+    openers
+}
+
+/// the openers of `devices` that have the device mapped, which is what capturing with V4L2's
+/// mmap streaming looks like. opening it to query the capabilities doesn't map anything
+#[cfg(target_os = "linux")]
+pub fn find_streaming(devices: &[PathBuf]) -> Vec<ProcessInfo> {
+    find_openers(devices)
+        .into_iter()
+        .filter(|process| is_mapped(process.pid, &process.device))
+        .collect()
+}
+
+/// runs a `/proc` scan like [`find_openers`] on a thread of its own and waits for it
+///
+/// not `spawn_blocking`: capabilities are per thread, and after `--user` only the thread that
+/// dropped privileges still has the ones the scan needs. a pooled thread from before the drop
+/// would come back with nothing, a new one starts with whatever the runtime thread has now
+#[cfg(target_os = "linux")]
+pub async fn scan<T: Send + 'static>(scan: impl FnOnce() -> T + Send + 'static) -> Option<T> {
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    std::thread::spawn(move || {
+        let _ = tx.send(scan());
+    });
+    rx.await.ok()
+}
+
+#[cfg(target_os = "linux")]
+fn is_mapped(pid: u32, device: &Path) -> bool {
+    let Ok(maps) = std::fs::read_to_string(format!("/proc/{}/maps", pid)) else {
+        return false;
+    };
+    let suffix = format!(" {}", device.display());
+
+    maps.lines().any(|line| line.ends_with(&suffix))
+}
+
+/// returns the first of `devices` that the process at `proc_dir` has open
+fn open_device(proc_dir: &Path, devices: &[PathBuf]) -> Option<PathBuf> {
+    let fds = std::fs::read_dir(proc_dir.join("fd")).ok()?;
+
+    fds.flatten()
+        .filter_map(|fd| std::fs::read_link(fd.path()).ok())
+        .find(|target| devices.contains(target))
+}
+
+/// works out which sandboxed app or container a process belongs to
+///
+/// flatpak apps show up as `bwrap` or a generic binary name, so the app id from the systemd scope in
+/// `/proc/<pid>/cgroup` (or `.flatpak-info` when the scope isn't there) tells you a lot more:
+///
+/// - `app-flatpak-us.zoom.Zoom-1234.scope` is flatpak `us.zoom.Zoom`
+/// - `snap.zoom-client.zoom-client-<uuid>.scope` is snap `zoom-client`
+/// - `docker-<id>.scope`, `/docker/<id>` and `libpod-<id>.scope` are containers, reported by
+///   their short id
+pub fn sandbox_app(pid: u32) -> Option<String> {
+    let cgroup = std::fs::read_to_string(format!("/proc/{}/cgroup", pid)).ok();
+
+    cgroup
+        .as_deref()
+        .and_then(|cgroup| cgroup.lines().find_map(app_from_cgroup))
+        .or_else(|| flatpak_info(pid))
+}
+
+fn app_from_cgroup(line: &str) -> Option<String> {
+    // `hierarchy-id:controllers:path`, the path can contain colons itself
+    let path = line.splitn(3, ':').nth(2)?;
+
+    path.rsplit('/')
+        .find_map(|unit| {
+            if let Some(rest) = unit.strip_prefix("app-flatpak-") {
+                // the app id is followed by `-<instance>.scope`
+                let (app, _) = rest.rsplit_once('-')?;
+                return Some(app.to_string());
+            }
+            if let Some(rest) = unit.strip_prefix("snap.") {
+                let (app, _) = rest.split_once('.')?;
+                return Some(format!("snap:{}", app));
+            }
+            for prefix in ["docker-", "libpod-"] {
+                if let Some(id) = unit.strip_prefix(prefix) {
+                    let id = id.trim_end_matches(".scope");
+                    return Some(format!("container:{}", short_id(id)));
+                }
+            }
+            None
+        })
+        .or_else(|| {
+            // cgroupfs docker setups use `/docker/<id>` with no scope unit
+            let id = path.strip_prefix("/docker/")?;
+            Some(format!("container:{}", short_id(id)))
+        })
+}
+
+/// the first 12 characters of a container id like `docker ps` shows, by characters since the
+/// cgroup path is whatever the runtime put there
+fn short_id(id: &str) -> String {
+    id.chars().take(12).collect()
+}
+
+/// the app id from a flatpak sandbox's `/.flatpak-info`, only readable with enough privileges
+fn flatpak_info(pid: u32) -> Option<String> {
+    let info = std::fs::read_to_string(format!("/proc/{}/root/.flatpak-info", pid)).ok()?;
+
+    info.lines()
+        .skip_while(|line| line.trim() != "[Application]")
+        .find_map(|line| line.strip_prefix("name="))
+        .map(|name| name.trim().to_string())
+}
+
+/// human readable list of opener names, deduped since apps like browsers open from several pids
+pub fn describe(openers: &[ProcessInfo]) -> Option<String> {
+    let mut names: Vec<&str> = openers.iter().map(ProcessInfo::display_name).collect();
+    names.sort_unstable();
+    names.dedup();
+
+    if names.is_empty() {
+        None
+    } else {
+        Some(names.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shortens_container_ids() {
+        let line = "0::/system.slice/docker-0123456789abcdef0123.scope";
+        assert_eq!(
+            app_from_cgroup(line).as_deref(),
+            Some("container:0123456789ab")
+        );
+    }
+
+    #[test]
+    fn does_not_split_non_ascii_ids() {
+        assert_eq!(
+            app_from_cgroup("0::/docker/ééééééé").as_deref(),
+            Some("container:ééééééé")
+        );
+        assert_eq!(
+            app_from_cgroup("0::/docker/aaaaaaaaaaaé").as_deref(),
+            Some("container:aaaaaaaaaaaé")
+        );
+    }
+}
@@ -0,0 +1,55 @@
+use std::time::{Duration, SystemTime};
+
+use tokio::sync::watch;
+use tokio::time::Instant;
+
+/// how often to compare the clocks when there's no logind to ask
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+/// how far the wall clock has to get ahead of the monotonic clock to count as a resume, a bit of
+/// NTP slewing shouldn't set it off
+const CLOCK_JUMP: Duration = Duration::from_secs(10);
+
+/// tells everyone subscribed when the events so far can't be trusted and the cameras need
+/// checking again, the value says why
+///
+/// this notices the machine waking up from suspend by itself. on linux with the `dbus` feature
+/// logind's `PrepareForSleep` says so directly, without it the wall clock gets compared with the monotonic one, which stands
+/// still while suspended. backends that lose events send on it too
+pub fn watch() -> watch::Sender<&'static str> {
+    let (tx, _) = watch::channel("startup");
+    let resync = tx.clone();
+
+    tokio::spawn(async move {
+        #[cfg(all(target_os = "linux", feature = "dbus"))]
+        match crate::dbus::watch_resume(&tx).await {
+            Ok(()) => tracing::warn!("lost logind, watching the clock for resumes instead"),
+            Err(e) => tracing::info!("can't watch logind for resumes, watching the clock: {}", e),
+        }
+
+        watch_clock(&tx).await;
+    });
+
+    resync
+}
+
+async fn watch_clock(tx: &watch::Sender<&'static str>) {
+    let mut ticker = tokio::time::interval(CHECK_INTERVAL);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let mut last = (SystemTime::now(), Instant::now());
+
+    loop {
+        ticker.tick().await;
+
+        let now = (SystemTime::now(), Instant::now());
+        let wall = now.0.duration_since(last.0).unwrap_or_default();
+        let monotonic = now.1 - last.1;
+        if wall > monotonic + CLOCK_JUMP {
+            tracing::info!(
+                "wall clock jumped {:?} ahead, assuming a resume",
+                wall - monotonic
+            );
+            tx.send_replace("a resume");
+        }
+        last = now;
+    }
+}
@@ -0,0 +1,90 @@
+use std::time::Duration;
+
+use chrono::{Datelike, NaiveDateTime, NaiveTime, Weekday};
+use serde::Deserialize;
+
+/// a daily time window like `09:00-17:00`, `22:00-07:00` runs over midnight
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(try_from = "String")]
+pub struct Window {
+    start: NaiveTime,
+    end: NaiveTime,
+}
+
+impl TryFrom<String> for Window {
+    type Error = String;
+
+    fn try_from(window: String) -> Result<Self, Self::Error> {
+        let parse = |time: &str| {
+            NaiveTime::parse_from_str(time.trim(), "%H:%M")
+                .map_err(|e| format!("invalid time `{}` in `{}`: {}", time, window, e))
+        };
+        let (start, end) = window
+            .split_once('-')
+            .ok_or_else(|| format!("expected a window like 09:00-17:00, got `{}`", window))?;
+
+        Ok(Self {
+            start: parse(start)?,
+            end: parse(end)?,
+        })
+    }
+}
+
+impl Window {
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+/// times when state changes are tracked but not shown anywhere
+///
+/// ```toml
+/// [quiet_hours]
+/// windows = ["22:00-07:00"]
+/// days = ["sat", "sun"]
+/// topic = "camera-snitch/quiet"
+/// ```
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct QuietHours {
+    /// quiet every day during these windows
+    pub windows: Vec<Window>,
+    /// quiet all day on these days
+    pub days: Vec<Weekday>,
+    /// publish the state here while quiet, leave out to not publish it at all
+    pub topic: Option<String>,
+}
+
+impl QuietHours {
+    pub fn is_enabled(&self) -> bool {
+        !self.windows.is_empty() || !self.days.is_empty()
+    }
+
+    pub fn is_quiet(&self, now: NaiveDateTime) -> bool {
+        self.days.contains(&now.weekday()) || self.windows.iter().any(|w| w.contains(now.time()))
+    }
+
+    /// how long until the next window starts or ends, or the day changes
+    pub fn until_change(&self, now: NaiveDateTime) -> Duration {
+        let today = now.date();
+        let tomorrow = today + chrono::Days::new(1);
+
+        let mut next = tomorrow.and_time(NaiveTime::MIN);
+        for window in &self.windows {
+            for time in [window.start, window.end] {
+                for day in [today, tomorrow] {
+                    let at = day.and_time(time);
+                    if at > now && at < next {
+                        next = at;
+                    }
+                }
+            }
+        }
+
+        (next - now).to_std().unwrap_or(Duration::from_secs(60))
+    }
+}
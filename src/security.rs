@@ -0,0 +1,87 @@
+use std::collections::HashSet;
+#[cfg(feature = "alerts")]
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::process::ProcessInfo;
+
+/// flags processes that open a camera without being on the `expected_processes` list, a basic
+/// tripwire for software that shouldn't be looking
+pub struct Tripwire {
+    expected: Vec<String>,
+    #[cfg(feature = "alerts")]
+    webhook: Option<(reqwest::Client, String)>,
+    /// pids we already raised the alarm for, so a process gets one alert however often it opens
+    alerted: HashSet<u32>,
+}
+
+impl Tripwire {
+    /// `None` unless `[security]` is enabled in the config
+    pub fn from_config(config: &Config) -> anyhow::Result<Option<Self>> {
+        if !config.security.enabled {
+            return Ok(None);
+        }
+
+        #[cfg(not(feature = "alerts"))]
+        if config.security.webhook.is_some() {
+            anyhow::bail!("the security `webhook` needs a build with the `alerts` feature");
+        }
+        #[cfg(feature = "alerts")]
+        let webhook = match &config.security.webhook {
+            Some(url) => Some((
+                reqwest::Client::builder()
+                    .timeout(Duration::from_secs(10))
+                    .build()?,
+                url.clone(),
+            )),
+            None => None,
+        };
+
+        Ok(Some(Self {
+            expected: config.expected_processes.clone(),
+            #[cfg(feature = "alerts")]
+            webhook,
+            alerted: HashSet::new(),
+        }))
+    }
+
+    /// the processes in `processes` that should set off an alert
+    pub fn check<'a>(&mut self, processes: &'a [ProcessInfo]) -> Vec<&'a ProcessInfo> {
+        // forget processes that are gone, pids get reused
+        self.alerted
+            .retain(|pid| std::path::Path::new(&format!("/proc/{}", pid)).exists());
+
+        processes
+            .iter()
+            .filter(|p| !p.is_expected(&self.expected))
+            .filter(|p| self.alerted.insert(p.pid))
+            .collect()
+    }
+
+    /// posts the process details to the webhook in the background, if there is one
+    #[cfg(feature = "alerts")]
+    pub fn send_webhook(&self, process: &ProcessInfo) {
+        let Some((http, url)) = &self.webhook else {
+            return;
+        };
+
+        let request = http.post(url).json(&alert_payload(process));
+        tokio::spawn(async move {
+            let res = request
+                .send()
+                .await
+                .and_then(reqwest::Response::error_for_status);
+            if let Err(e) = res {
+                tracing::error!("error sending security webhook: {}", e);
+            }
+        });
+    }
+}
+
+/// what gets sent to the webhook and the MQTT security topic
+pub fn alert_payload(process: &ProcessInfo) -> serde_json::Value {
+    serde_json::json!({
+        "timestamp": chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        "process": process,
+    })
+}
@@ -0,0 +1,192 @@
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use rusqlite::{params, Connection};
+
+use crate::CameraState;
+
+/// records every camera session into a local SQLite database for `camera-notifier sessions`
+pub struct SessionLog {
+    conn: Connection,
+    /// row id and start time of the session in progress
+    current: Option<(i64, i64)>,
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+fn open(path: &Path) -> anyhow::Result<Connection> {
+    let conn = Connection::open(path)
+        .with_context(|| format!("opening session database {}", path.display()))?;
+
+    // times are unix seconds, `end` and `duration` stay NULL while a session is in progress or
+    // if the daemon died before it ended
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS sessions (
+            id INTEGER PRIMARY KEY,
+            device TEXT,
+            process TEXT,
+            start INTEGER NOT NULL,
+            end INTEGER,
+            duration INTEGER
+        );",
+    )?;
+
+    Ok(conn)
+}
+
+impl SessionLog {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        Ok(Self {
+            conn: open(path)?,
+            current: None,
+        })
+    }
+
+    /// carries on with the last session after a restart that picked it up, instead of leaving it
+    /// cut short at the shutdown and counting the rest as nothing
+    pub fn reopen(&mut self) {
+        let res = self.conn.query_row(
+            "SELECT id, start FROM sessions ORDER BY id DESC LIMIT 1",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        );
+        let (id, start) = match res {
+            Ok(session) => session,
+            Err(rusqlite::Error::QueryReturnedNoRows) => return,
+            Err(e) => {
+                tracing::error!("error reopening the last session: {}", e);
+                return;
+            }
+        };
+
+        match self.conn.execute(
+            "UPDATE sessions SET end = NULL, duration = NULL WHERE id = ?1",
+            params![id],
+        ) {
+            Ok(_) => self.current = Some((id, start)),
+            Err(e) => tracing::error!("error reopening the last session: {}", e),
+        }
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub fn record(&mut self, state: &CameraState, device: Option<&Path>, process: Option<&str>) {
+        if let Err(e) = self.try_record(state, device, process) {
+            tracing::error!("error recording session: {}", e);
+        }
+    }
+
+    fn try_record(
+        &mut self,
+        state: &CameraState,
+        device: Option<&Path>,
+        process: Option<&str>,
+    ) -> rusqlite::Result<()> {
+        let now = now();
+
+        match state {
+            CameraState::On => {
+                self.conn.execute(
+                    "INSERT INTO sessions (device, process, start) VALUES (?1, ?2, ?3)",
+                    params![device.map(|d| d.display().to_string()), process, now],
+                )?;
+                self.current = Some((self.conn.last_insert_rowid(), now));
+            }
+            CameraState::Off => {
+                if let Some((id, start)) = self.current.take() {
+                    self.conn.execute(
+                        "UPDATE sessions SET end = ?1, duration = ?2 WHERE id = ?3",
+                        params![now, now - start, id],
+                    )?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// prints the most recent sessions, newest first
+pub fn print_recent(path: &Path, limit: u32) -> anyhow::Result<()> {
+    let conn = open(path)?;
+    let mut stmt = conn.prepare(
+        "SELECT datetime(start, 'unixepoch', 'localtime'),
+                datetime(end, 'unixepoch', 'localtime'),
+                duration, device, process
+         FROM sessions ORDER BY start DESC LIMIT ?1",
+    )?;
+
+    println!(
+        "{:<19}  {:<19}  {:>9}  {:<12}  PROCESS",
+        "START", "END", "DURATION", "DEVICE"
+    );
+
+    let rows = stmt.query_map(params![limit], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, Option<String>>(1)?,
+            row.get::<_, Option<i64>>(2)?,
+            row.get::<_, Option<String>>(3)?,
+            row.get::<_, Option<String>>(4)?,
+        ))
+    })?;
+
+    for row in rows {
+        let (start, end, duration, device, process) = row?;
+        let duration = duration
+            .map(|d| format!("{}m{:02}s", d / 60, d % 60))
+            .unwrap_or_else(|| "-".to_string());
+
+        println!(
+            "{:<19}  {:<19}  {:>9}  {:<12}  {}",
+            start,
+            end.as_deref().unwrap_or("-"),
+            duration,
+            device.as_deref().unwrap_or("-"),
+            process.as_deref().unwrap_or("-"),
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reopened_session_keeps_its_row() {
+        let mut log = SessionLog::open(Path::new(":memory:")).unwrap();
+        log.record(
+            &CameraState::On,
+            Some(Path::new("/dev/video0")),
+            Some("zoom"),
+        );
+        // the restart
+        log.record(&CameraState::Off, None, None);
+        log.current = None;
+
+        log.reopen();
+        let (end, duration): (Option<i64>, Option<i64>) = log
+            .conn
+            .query_row("SELECT end, duration FROM sessions", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert_eq!((end, duration), (None, None));
+
+        log.record(&CameraState::Off, None, None);
+        let (rows, ended): (i64, i64) = log
+            .conn
+            .query_row("SELECT count(*), count(end) FROM sessions", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert_eq!((rows, ended), (1, 1));
+    }
+}
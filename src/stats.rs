@@ -0,0 +1,220 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{DateTime, Datelike, Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+/// the counters behind the usage sensors, persisted so a restart doesn't zero out the day
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+struct Counters {
+    /// local date the daily counters belong to
+    day: NaiveDate,
+    today_secs: u64,
+    today_sessions: u32,
+    /// monday of the ISO week the weekly counters belong to
+    week: NaiveDate,
+    week_secs: u64,
+    week_sessions: u32,
+}
+
+fn week_start(day: NaiveDate) -> NaiveDate {
+    day - chrono::Days::new(day.weekday().num_days_from_monday() as u64)
+}
+
+impl Counters {
+    fn new(day: NaiveDate) -> Self {
+        Self {
+            day,
+            today_secs: 0,
+            today_sessions: 0,
+            week: week_start(day),
+            week_secs: 0,
+            week_sessions: 0,
+        }
+    }
+}
+
+/// daily and weekly camera usage, reset at local midnight and on monday respectively
+pub struct UsageStats {
+    path: Option<PathBuf>,
+    counters: Counters,
+    /// camera time not yet making up a whole second, carried over to the next span
+    remainder: Duration,
+}
+
+impl UsageStats {
+    /// loads the counters from `path` if there is one, starting from zero if it's missing or unreadable
+    pub fn load(path: Option<&Path>) -> Self {
+        let today = Local::now().date_naive();
+
+        let counters = path
+            .and_then(|path| match std::fs::read_to_string(path) {
+                Ok(contents) => serde_json::from_str(&contents)
+                    .map_err(|e| tracing::warn!("ignoring unreadable stats file: {}", e))
+                    .ok(),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+                Err(e) => {
+                    tracing::warn!("ignoring unreadable stats file: {}", e);
+                    None
+                }
+            })
+            .unwrap_or_else(|| Counters::new(today));
+
+        let mut stats = Self {
+            path: path.map(Path::to_path_buf),
+            counters,
+            remainder: Duration::ZERO,
+        };
+        stats.roll_over();
+
+        stats
+    }
+
+    /// resets whichever counters belong to a day or week that has passed, returns true if it did
+    pub fn roll_over(&mut self) -> bool {
+        self.roll_over_to(Local::now().date_naive())
+    }
+
+    fn roll_over_to(&mut self, today: NaiveDate) -> bool {
+        if self.counters.day == today {
+            return false;
+        }
+
+        let mut counters = Counters::new(today);
+        if self.counters.week == counters.week {
+            counters.week_secs = self.counters.week_secs;
+            counters.week_sessions = self.counters.week_sessions;
+        }
+        self.counters = counters;
+        self.save();
+
+        true
+    }
+
+    pub fn session_started(&mut self) {
+        self.roll_over();
+        self.counters.today_sessions += 1;
+        self.counters.week_sessions += 1;
+        self.save();
+    }
+
+    /// adds the camera time that ended just now, a span running over midnight gets split between
+    /// the two days
+    pub fn add_usage(&mut self, usage: Duration) {
+        self.add_usage_at(usage, Local::now());
+    }
+
+    fn add_usage_at(&mut self, usage: Duration, now: DateTime<Local>) {
+        let today = now.date_naive();
+        let since_midnight = today
+            .and_hms_opt(0, 0, 0)
+            .and_then(|midnight| midnight.and_local_timezone(Local).earliest())
+            .and_then(|midnight| (now - midnight).to_std().ok())
+            .unwrap_or(usage);
+
+        // the part before midnight belongs to the day that's over, while the counters are still on it
+        if usage > since_midnight && self.counters.day != today {
+            self.accrue(usage - since_midnight);
+        }
+        self.roll_over_to(today);
+        self.accrue(usage.min(since_midnight));
+        self.save();
+    }
+
+    fn accrue(&mut self, usage: Duration) {
+        let total = self.remainder + usage;
+        let secs = total.as_secs();
+        self.counters.today_secs += secs;
+        self.counters.week_secs += secs;
+        self.remainder = total - Duration::from_secs(secs);
+    }
+
+    pub fn minutes_today(&self) -> u64 {
+        self.counters.today_secs / 60
+    }
+
+    pub fn sessions_today(&self) -> u32 {
+        self.counters.today_sessions
+    }
+
+    pub fn minutes_this_week(&self) -> u64 {
+        self.counters.week_secs / 60
+    }
+
+    pub fn sessions_this_week(&self) -> u32 {
+        self.counters.week_sessions
+    }
+
+    fn save(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+
+        let res = serde_json::to_string(&self.counters)
+            .map_err(anyhow::Error::from)
+            .and_then(|contents| Ok(std::fs::write(path, contents)?));
+        if let Err(e) = res {
+            tracing::error!("error saving stats file {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// how long until the next local midnight, when the daily counters roll over
+pub fn until_midnight() -> Duration {
+    let now = Local::now();
+    let midnight = (now.date_naive() + chrono::Days::new(1))
+        .and_hms_opt(0, 0, 0)
+        .and_then(|midnight| midnight.and_local_timezone(Local).earliest());
+
+    midnight
+        .and_then(|midnight| (midnight - now).to_std().ok())
+        // DST weirdness, just check again in a minute
+        .unwrap_or(Duration::from_secs(60))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(day: NaiveDate) -> UsageStats {
+        UsageStats {
+            path: None,
+            counters: Counters::new(day),
+            remainder: Duration::ZERO,
+        }
+    }
+
+    fn local(day: NaiveDate, h: u32, m: u32, s: u32) -> DateTime<Local> {
+        day.and_hms_opt(h, m, s)
+            .unwrap()
+            .and_local_timezone(Local)
+            .earliest()
+            .unwrap()
+    }
+
+    #[test]
+    fn carries_the_sub_second_remainder() {
+        // a wednesday
+        let day = NaiveDate::from_ymd_opt(2024, 5, 15).unwrap();
+        let mut stats = stats(day);
+        for _ in 0..10 {
+            stats.add_usage_at(Duration::from_millis(1500), local(day, 12, 0, 0));
+        }
+
+        assert_eq!(stats.counters.today_secs, 15);
+        assert_eq!(stats.counters.week_secs, 15);
+    }
+
+    #[test]
+    fn splits_a_span_over_midnight() {
+        let yesterday = NaiveDate::from_ymd_opt(2024, 5, 14).unwrap();
+        let today = NaiveDate::from_ymd_opt(2024, 5, 15).unwrap();
+        let mut stats = stats(yesterday);
+        stats.add_usage_at(Duration::from_secs(90), local(today, 0, 0, 30));
+
+        assert_eq!(stats.counters.day, today);
+        assert_eq!(stats.counters.today_secs, 30);
+        // same week, so the minute before midnight still counts towards it
+        assert_eq!(stats.counters.week_secs, 90);
+    }
+}
@@ -0,0 +1,74 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+use crate::CameraState;
+
+/// what the daemon knows right now, kept in a watch channel for whatever wants to ask
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Status {
+    /// the published state, on while any camera is in use
+    pub state: CameraState,
+    /// the device behind the last change, `None` until there's been one
+    pub device: Option<PathBuf>,
+    pub used_by: Option<String>,
+    /// RFC 3339 timestamp of the last change
+    pub since: Option<String>,
+    /// each device's own state, before debouncing
+    pub devices: BTreeMap<PathBuf, CameraState>,
+    /// whether we're connected to the broker, `None` when not publishing to MQTT
+    #[serde(skip)]
+    pub mqtt_connected: Option<bool>,
+}
+
+/// how many events an API client can fall behind before it starts missing them
+const EVENT_BUFFER: usize = 256;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    /// a device was opened or closed, before debouncing
+    DeviceOpened,
+    DeviceClosed,
+    /// the published state changed
+    StateChanged,
+}
+
+/// something that happened, for API clients that want every step rather than the `Status` it
+/// ends up at. an open and close in quick succession don't leave anything behind in the status
+#[derive(Serialize, Debug, Clone)]
+pub struct Event {
+    pub kind: EventKind,
+    pub device: PathBuf,
+    /// the device's state for device events, the published one for state changes
+    pub state: CameraState,
+    pub used_by: Option<String>,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl Event {
+    pub fn new(
+        kind: EventKind,
+        device: PathBuf,
+        state: CameraState,
+        used_by: Option<String>,
+    ) -> Self {
+        Self {
+            kind,
+            device,
+            state,
+            used_by,
+            timestamp: Utc::now(),
+        }
+    }
+}
+
+/// where the main loop sends every `Event`, API clients subscribe to it
+///
+/// sending with nobody subscribed is fine, the events just go nowhere
+pub fn events() -> broadcast::Sender<Event> {
+    broadcast::channel(EVENT_BUFFER).0
+}
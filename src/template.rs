@@ -0,0 +1,110 @@
+/// expands `{name}` placeholders in `template` with the matching value from `vars`
+///
+/// unknown placeholders are left alone, so JSON payloads like `{"app": "{process}"}` don't need
+/// any escaping
+pub fn render(template: &str, vars: &[(&str, &str)]) -> String {
+    render_with(template, vars, |value, rendered| rendered.push_str(value))
+}
+
+/// `render` for JSON templates, the values get escaped so a quote in something like a window
+/// title can't break out of the string it's put in
+pub fn render_json(template: &str, vars: &[(&str, &str)]) -> String {
+    render_with(template, vars, |value, rendered| {
+        let quoted = serde_json::Value::from(value).to_string();
+        rendered.push_str(&quoted[1..quoted.len() - 1]);
+    })
+}
+
+/// the names of the `{name}` placeholders in `template`, for checking a template only uses the
+/// ones that get filled in for it
+pub fn placeholders(template: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        rest = &rest[start + 1..];
+        let Some(end) = rest.find('}') else {
+            break;
+        };
+        let name = &rest[..end];
+        if !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            names.push(name);
+            rest = &rest[end + 1..];
+        }
+    }
+
+    names
+}
+
+/// one pass over `template`, so a value with `{name}` in it doesn't get expanded again
+fn render_with(template: &str, vars: &[(&str, &str)], push: impl Fn(&str, &mut String)) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let var = after.find('}').and_then(|end| {
+            vars.iter()
+                .find(|(name, _)| *name == &after[..end])
+                .map(|(_, value)| (end, *value))
+        });
+
+        match var {
+            Some((end, value)) => {
+                push(value, &mut rendered);
+                rest = &after[end + 1..];
+            }
+            None => {
+                rendered.push('{');
+                rest = after;
+            }
+        }
+    }
+    rendered.push_str(rest);
+
+    rendered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn leaves_unknown_placeholders_and_json_alone() {
+        let rendered = render(
+            r#"{"app": "{process}", "x": "{other}"}"#,
+            &[("process", "zoom")],
+        );
+        assert_eq!(rendered, r#"{"app": "zoom", "x": "{other}"}"#);
+    }
+
+    #[test]
+    fn does_not_expand_placeholders_in_values() {
+        let vars = [("process", "{device}"), ("device", "/dev/video0")];
+        assert_eq!(
+            render("{process} on {device}", &vars),
+            "{device} on /dev/video0"
+        );
+    }
+
+    #[test]
+    fn finds_placeholders() {
+        assert_eq!(
+            placeholders("home/{hostname}/{device}/state"),
+            ["hostname", "device"]
+        );
+        assert_eq!(
+            placeholders(r#"{"app": "{process}"} {unclosed"#),
+            ["process"]
+        );
+        assert!(placeholders("homeassistant/state").is_empty());
+    }
+
+    #[test]
+    fn escapes_values_in_json() {
+        let vars = [("process", r#"say "hi" \o/"#)];
+        let rendered = render_json(r#"{"app": "{process}"}"#, &vars);
+        assert_eq!(rendered, r#"{"app": "say \"hi\" \\o/"}"#);
+        assert!(serde_json::from_str::<serde_json::Value>(&rendered).is_ok());
+    }
+}
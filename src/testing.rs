@@ -0,0 +1,191 @@
+//! fakes for driving the whole daemon without cameras or a broker, behind the `testing` feature
+//!
+//! `ScriptedMonitor` replays device events from a script in place of a real backend, and
+//! `Capture` is what a publisher made with `Publisher::capture` hands its publishes to instead of
+//! a broker. [`run`] puts the two together with the daemon's debounce, state and discovery in
+//! between, the binary does the same for `run --script` and `--output capture`
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Context;
+use clap::Parser;
+use tokio::sync::mpsc;
+
+pub use crate::mqtt::Publisher;
+
+use crate::monitor::{DeviceEvent, DeviceEventKind, DeviceMonitor};
+use crate::process::ProcessInfo;
+use crate::{Args, Command};
+
+/// the process a scripted event is blamed on, like the eBPF backend would report it
+#[derive(serde::Deserialize, Debug, Clone)]
+struct ScriptedProcess {
+    pid: u32,
+    name: String,
+    #[serde(default)]
+    exe: Option<PathBuf>,
+    #[serde(default)]
+    app: Option<String>,
+}
+
+/// one line of a script, waits `after_ms` since the previous step and then sends the event, if
+/// there is one. a bare `{"after_ms": 1000}` just waits, e.g. for a debounce to run out before
+/// the script ends
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct Step {
+    #[serde(default)]
+    after_ms: u64,
+    device: Option<PathBuf>,
+    kind: Option<DeviceEventKind>,
+    #[serde(default)]
+    process: Option<ScriptedProcess>,
+}
+
+impl Step {
+    fn event(&self) -> Option<DeviceEvent> {
+        let device = self.device.clone()?;
+        let process = self.process.clone().map(|process| ProcessInfo {
+            pid: process.pid,
+            name: process.name,
+            exe: process.exe,
+            app: process.app,
+            device: device.clone(),
+        });
+
+        Some(DeviceEvent {
+            device,
+            kind: self.kind?,
+            process,
+        })
+    }
+}
+
+/// a monitor playing back a script of device events, the channel closes once it's through
+pub struct ScriptedMonitor {
+    steps: Vec<Step>,
+}
+
+impl ScriptedMonitor {
+    pub fn new(steps: Vec<Step>) -> Self {
+        Self { steps }
+    }
+
+    /// reads a script with one JSON step per line, like
+    /// `{"after_ms": 100, "device": "/dev/video0", "kind": "opened"}`
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("reading script {}", path.display()))?;
+        Self::parse(&contents).with_context(|| format!("in {}", path.display()))
+    }
+
+    /// a script that's already been read, one JSON step per line
+    pub fn parse(script: &str) -> anyhow::Result<Self> {
+        let steps = script
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(n, line)| serde_json::from_str(line).with_context(|| format!("line {}", n + 1)))
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(Self::new(steps))
+    }
+
+    /// the devices there at startup, every one the script mentions except those it adds itself
+    pub fn devices(&self) -> Vec<PathBuf> {
+        let mut devices: Vec<PathBuf> = Vec::new();
+        let mut added = Vec::new();
+        for step in &self.steps {
+            let Some(device) = &step.device else {
+                continue;
+            };
+            if devices.contains(device) || added.contains(device) {
+                continue;
+            }
+            match step.kind {
+                Some(DeviceEventKind::Added) => added.push(device.clone()),
+                _ => devices.push(device.clone()),
+            }
+        }
+
+        devices
+    }
+}
+
+impl DeviceMonitor for ScriptedMonitor {
+    fn start(self: Box<Self>, tx: mpsc::Sender<DeviceEvent>) -> anyhow::Result<()> {
+        tokio::spawn(async move {
+            for step in self.steps {
+                tokio::time::sleep(Duration::from_millis(step.after_ms)).await;
+                if let Some(event) = step.event() {
+                    if tx.send(event).await.is_err() {
+                        return;
+                    }
+                }
+            }
+            tracing::info!("script finished");
+        });
+
+        Ok(())
+    }
+}
+
+/// a publish that went to a `Capture`
+#[derive(serde::Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Published {
+    pub topic: String,
+    pub retain: bool,
+    pub payload: String,
+}
+
+/// everything a capturing publisher published, in order, shared with whoever wants to look
+#[derive(Debug, Clone, Default)]
+pub struct Capture(Arc<Mutex<Vec<Published>>>);
+
+impl Capture {
+    pub fn push(&self, topic: String, retain: bool, payload: &[u8]) {
+        let published = Published {
+            topic,
+            retain,
+            payload: String::from_utf8_lossy(payload).into_owned(),
+        };
+        self.0.lock().unwrap().push(published);
+    }
+
+    pub fn publishes(&self) -> Vec<Published> {
+        self.0.lock().unwrap().clone()
+    }
+
+    /// prints every publish as a JSON line, for `--output capture`
+    pub fn print(&self) -> anyhow::Result<()> {
+        for published in self.publishes() {
+            println!("{}", serde_json::to_string(&published)?);
+        }
+
+        Ok(())
+    }
+}
+
+/// runs the daemon with `monitor` in place of the cameras at `devices`, and everything it
+/// publishes going to `publisher`, usually `Publisher::capture`
+///
+/// `args` are options for `camera-notifier run`, `--output capture` is already set. returns
+/// once the monitor's channel has closed and the daemon has shut down like it got SIGTERM
+pub async fn run(
+    args: &[&str],
+    devices: Vec<PathBuf>,
+    monitor: Box<dyn DeviceMonitor>,
+    publisher: Publisher,
+) -> anyhow::Result<()> {
+    let args = Args::try_parse_from(
+        ["camera-notifier", "run", "--output", "capture"]
+            .into_iter()
+            .chain(args.iter().copied()),
+    )?;
+    let Command::Run(run) = &args.command else {
+        unreachable!("always parsed as `run`");
+    };
+
+    crate::pipeline(&args, run, Some((devices, monitor)), Some(publisher)).await
+}
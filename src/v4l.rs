@@ -0,0 +1,76 @@
+use std::path::{Path, PathBuf};
+
+/// what sysfs knows about a camera, for the HA device registry
+///
+/// everything is optional, cameras that aren't V4L2 devices or aren't on USB just end up with
+/// less of it
+#[derive(Debug, Default)]
+pub struct CameraInfo {
+    /// the V4L2 card name, e.g. `HD Pro Webcam C920`
+    pub name: Option<String>,
+    pub manufacturer: Option<String>,
+    /// the USB product string, falling back to the card name
+    pub model: Option<String>,
+    /// USB `vendor:product` ids
+    pub usb_id: Option<String>,
+    pub serial: Option<String>,
+}
+
+fn read(path: &Path) -> Option<String> {
+    let contents = std::fs::read_to_string(path).ok()?;
+    let contents = contents.trim();
+
+    (!contents.is_empty()).then(|| contents.to_string())
+}
+
+fn sysfs(device: &Path) -> Option<PathBuf> {
+    Some(PathBuf::from("/sys/class/video4linux").join(device.file_name()?))
+}
+
+/// the USB device a node belongs to, `device` points at the USB interface and the descriptors
+/// live on the USB device above it
+fn usb_device(sysfs: &Path) -> Option<PathBuf> {
+    std::fs::canonicalize(sysfs.join("device"))
+        .ok()
+        .and_then(|interface| interface.parent().map(Path::to_path_buf))
+        .filter(|usb| usb.join("idVendor").exists())
+}
+
+/// the hardware behind a `/dev/videoN` node in sysfs, the same for every node of one camera
+///
+/// that's the USB device for USB cameras, so the RGB and IR nodes of a Windows Hello camera (on
+/// different interfaces) end up together, and whatever `device` points at for the rest
+pub fn physical_device(device: &Path) -> Option<PathBuf> {
+    let sysfs = sysfs(device)?;
+
+    usb_device(&sysfs).or_else(|| std::fs::canonicalize(sysfs.join("device")).ok())
+}
+
+/// looks up `device` (a `/dev/videoN` path) under `/sys/class/video4linux`
+pub fn camera_info(device: &Path) -> CameraInfo {
+    let Some(sysfs) = sysfs(device) else {
+        return CameraInfo::default();
+    };
+    let name = read(&sysfs.join("name"));
+
+    let Some(usb) = usb_device(&sysfs) else {
+        return CameraInfo {
+            model: name.clone(),
+            name,
+            ..Default::default()
+        };
+    };
+
+    let usb_id = match (read(&usb.join("idVendor")), read(&usb.join("idProduct"))) {
+        (Some(vendor), Some(product)) => Some(format!("{}:{}", vendor, product)),
+        _ => None,
+    };
+
+    CameraInfo {
+        manufacturer: read(&usb.join("manufacturer")),
+        model: read(&usb.join("product")).or_else(|| name.clone()),
+        usb_id,
+        serial: read(&usb.join("serial")),
+        name,
+    }
+}
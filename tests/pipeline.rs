@@ -0,0 +1,66 @@
+//! drives the daemon through the library like a downstream test suite would
+#![cfg(feature = "testing")]
+
+use camera_notifier::testing::{self, Capture, Publisher, ScriptedMonitor};
+
+const STATE: &str = "homeassistant/binary_sensor/officecamera/state";
+
+/// plays `script` through the daemon, everything it published comes back in order
+async fn run(script: &str) -> Vec<testing::Published> {
+    let script = ScriptedMonitor::parse(script).unwrap();
+    let capture = Capture::default();
+
+    Box::pin(testing::run(
+        &["--debounce-duration", "1000"],
+        script.devices(),
+        Box::new(script),
+        Publisher::capture(capture.clone()),
+    ))
+    .await
+    .unwrap();
+
+    capture.publishes()
+}
+
+fn payloads<'a>(published: &'a [testing::Published], topic: &str) -> Vec<&'a str> {
+    published
+        .iter()
+        .filter(|published| published.topic == topic)
+        .map(|published| published.payload.as_str())
+        .collect()
+}
+
+#[tokio::test(start_paused = true)]
+async fn discovery_comes_before_the_state() {
+    let published = run(r#"{"after_ms": 5000}"#).await;
+
+    let discovery = published
+        .iter()
+        .position(|published| published.topic.ends_with("/config"))
+        .expect("no discovery published");
+    let state = published
+        .iter()
+        .position(|published| published.topic == STATE)
+        .expect("no state published");
+    assert!(discovery < state);
+    assert_eq!(payloads(&published, STATE), ["OFF"]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn on_until_the_last_camera_closes() {
+    let published = run(r#"
+{"after_ms": 50, "device": "/dev/video0", "kind": "opened", "process": {"pid": 42, "name": "zoom"}}
+{"after_ms": 2000, "device": "/dev/video2", "kind": "opened", "process": {"pid": 7, "name": "obs"}}
+{"after_ms": 2000, "device": "/dev/video0", "kind": "closed"}
+{"after_ms": 2000, "device": "/dev/video2", "kind": "closed"}
+{"after_ms": 5000}
+"#)
+    .await;
+
+    assert_eq!(payloads(&published, STATE), ["OFF", "ON", "OFF"]);
+    let video0 = payloads(
+        &published,
+        "homeassistant/binary_sensor/officecamera/video0/state",
+    );
+    assert_eq!(video0.last(), Some(&"OFF"));
+}